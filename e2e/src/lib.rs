@@ -0,0 +1,342 @@
+//! A fake DNS-over-HTTPS server standing in for the resolvers and the crawler operators'
+//! IP range lists, so that the service runs in Viceroy without reaching the internet.
+//!
+//! It answers with the records of a fixture file, both over the Google DNS JSON API on
+//! `/resolve` and over RFC 8484 on `/dns-query`, with DNS messages either base64url-encoded in
+//! the `?dns=` query string of a GET request or in the body of a POST request. It also serves
+//! the fixture's IP range lists at their paths. Anything else is a 404.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{Ipv4Addr, Ipv6Addr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+
+/// The address the local server backends in `fastly.toml` point at.
+pub const ADDR: &str = "127.0.0.1:8053";
+
+/// The fixture the end-to-end tests run against.
+pub const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/fixtures/dns.json");
+
+/// The TTL, in seconds, of every answer.
+const TTL: u32 = 300;
+
+/// The DNS response code of a successful query.
+const RCODE_NOERROR: u16 = 0;
+
+/// The DNS response code of a query the resolver failed to answer.
+const RCODE_SERVFAIL: u16 = 2;
+
+/// The DNS response code of a query for a name that does not exist.
+const RCODE_NXDOMAIN: u16 = 3;
+
+/// The DNS resource record type code of an IPv4 address (A) record.
+const TYPE_A: u16 = 1;
+
+/// The DNS resource record type code of a domain name pointer (PTR) record.
+const TYPE_PTR: u16 = 12;
+
+/// The DNS resource record type code of an IPv6 address (AAAA) record.
+const TYPE_AAAA: u16 = 28;
+
+/// The DNS class of Internet records.
+const CLASS_IN: u16 = 1;
+
+/// The length of a DNS message header.
+const HEADER_LEN: usize = 12;
+
+/// The media type of the Google DNS JSON API responses and of the IP range lists.
+const JSON_CONTENT_TYPE: &str = "application/json";
+
+/// The media type of RFC 8484 DNS messages.
+const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+/// The canned answers of a [`FakeDoh`] server, e.g.
+/// `{"records": {"1.66.249.66.in-addr.arpa": {"PTR": ["crawl-66-249-66-1.googlebot.com."]}}}`.
+#[derive(Deserialize)]
+struct Fixture {
+    /// The records of every name that exists, by name and record type (`A`, `AAAA` or `PTR`).
+    /// A name without records of the queried type has an empty answer; a name missing
+    /// altogether does not exist (NXDOMAIN).
+    #[serde(default)]
+    records: HashMap<String, HashMap<String, Vec<String>>>,
+    /// The names whose queries fail with SERVFAIL.
+    #[serde(default)]
+    servfail: HashSet<String>,
+    /// The IP range lists served, by URL path, e.g.
+    /// `/static/search/apis/ipranges/googlebot.json`.
+    #[serde(default)]
+    ip_ranges: HashMap<String, Value>,
+}
+
+impl Fixture {
+    fn load(path: &Path) -> io::Result<Fixture> {
+        let fixture: Fixture = serde_json::from_slice(&std::fs::read(path)?)?;
+        Ok(Fixture {
+            records: fixture
+                .records
+                .into_iter()
+                .map(|(name, records)| (normalize_name(&name), records))
+                .collect(),
+            servfail: fixture
+                .servfail
+                .iter()
+                .map(|name| normalize_name(name))
+                .collect(),
+            ip_ranges: fixture.ip_ranges,
+        })
+    }
+
+    /// The response code and the records of a query for the `record_type` records of `name`.
+    fn lookup(&self, name: &str, record_type: &str) -> (u16, &[String]) {
+        let normalized_name = normalize_name(name);
+        if self.servfail.contains(&normalized_name) {
+            return (RCODE_SERVFAIL, &[]);
+        }
+        match self.records.get(&normalized_name) {
+            Some(records) => (
+                RCODE_NOERROR,
+                records
+                    .get(&record_type.to_ascii_uppercase())
+                    .map_or(&[], Vec::as_slice),
+            ),
+            None => (RCODE_NXDOMAIN, &[]),
+        }
+    }
+
+    /// The Google DNS JSON API response to a query for the `record_type` records of `name`.
+    fn resolve(&self, name: &str, record_type: &str) -> Value {
+        let normalized_name = normalize_name(name);
+        let (status, data) = self.lookup(name, record_type);
+        let record_type_code = match record_type.to_ascii_uppercase().as_str() {
+            "A" => TYPE_A,
+            "PTR" => TYPE_PTR,
+            "AAAA" => TYPE_AAAA,
+            _ => 0,
+        };
+        let answer: Vec<Value> = data
+            .iter()
+            .map(|data| {
+                json!({
+                    "name": format!("{}.", normalized_name),
+                    "type": record_type_code,
+                    "TTL": TTL,
+                    "data": data,
+                })
+            })
+            .collect();
+        json!({
+            "Status": status,
+            "TC": false,
+            "Question": [{"name": format!("{}.", normalized_name), "type": record_type_code}],
+            "Answer": answer,
+        })
+    }
+
+    /// The RFC 8484 response to the DNS message `query`, or `None` if it is not a query
+    /// with a single question.
+    fn resolve_message(&self, query: &[u8]) -> Option<Vec<u8>> {
+        if query.get(4..6)? != [0, 1] {
+            return None;
+        }
+        let mut labels = Vec::new();
+        let mut position = HEADER_LEN;
+        loop {
+            let len = usize::from(*query.get(position)?);
+            position += 1;
+            if len == 0 {
+                break;
+            }
+            labels.push(String::from_utf8_lossy(
+                query.get(position..position + len)?,
+            ));
+            position += len;
+        }
+        let record_type = u16::from_be_bytes(query.get(position..position + 2)?.try_into().ok()?);
+        let question = query.get(HEADER_LEN..position + 4)?;
+
+        let record_type_name = match record_type {
+            TYPE_A => "A",
+            TYPE_PTR => "PTR",
+            TYPE_AAAA => "AAAA",
+            _ => "",
+        };
+        let (rcode, data) = self.lookup(&labels.join("."), record_type_name);
+        let answers: Vec<Vec<u8>> = data
+            .iter()
+            .filter_map(|data| encode_record_data(record_type, data))
+            .collect();
+
+        let mut response = Vec::new();
+        // The ID of the query.
+        response.extend_from_slice(&query[..2]);
+        // A response (QR), keeping the recursion desired (RD) flag of the query, with
+        // recursion available (RA) and the response code.
+        response.extend_from_slice(&[0x80 | (query[2] & 0x01), 0x80 | rcode as u8]);
+        response.extend_from_slice(&1u16.to_be_bytes());
+        response.extend_from_slice(&(answers.len() as u16).to_be_bytes());
+        response.extend_from_slice(&[0; 4]);
+        response.extend_from_slice(question);
+        for data in answers {
+            // A pointer to the name of the question.
+            response.extend_from_slice(&[0xc0, HEADER_LEN as u8]);
+            response.extend_from_slice(&record_type.to_be_bytes());
+            response.extend_from_slice(&CLASS_IN.to_be_bytes());
+            response.extend_from_slice(&TTL.to_be_bytes());
+            response.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            response.extend_from_slice(&data);
+        }
+        Some(response)
+    }
+}
+
+/// The wire format of the `record_type` record `data` of the fixture, e.g. `66.249.66.1` for
+/// an A record, or `None` if it is not a valid one.
+fn encode_record_data(record_type: u16, data: &str) -> Option<Vec<u8>> {
+    match record_type {
+        TYPE_A => Some(data.parse::<Ipv4Addr>().ok()?.octets().to_vec()),
+        TYPE_AAAA => Some(data.parse::<Ipv6Addr>().ok()?.octets().to_vec()),
+        TYPE_PTR => {
+            let mut name = Vec::new();
+            for label in normalize_name(data).split('.') {
+                name.push(u8::try_from(label.len()).ok()?);
+                name.extend_from_slice(label.as_bytes());
+            }
+            name.push(0);
+            Some(name)
+        }
+        _ => None,
+    }
+}
+
+/// A fake DNS-over-HTTPS server, see the [crate] documentation.
+pub struct FakeDoh {
+    listener: TcpListener,
+    fixture: Arc<Fixture>,
+}
+
+impl FakeDoh {
+    /// Load the fixture at `fixture_path` and listen on `addr`.
+    pub fn bind(addr: &str, fixture_path: &Path) -> io::Result<FakeDoh> {
+        Ok(FakeDoh {
+            listener: TcpListener::bind(addr)?,
+            fixture: Arc::new(Fixture::load(fixture_path)?),
+        })
+    }
+
+    /// Serve requests until the process exits, each connection on its own thread.
+    pub fn serve(self) {
+        for stream in self.listener.incoming().flatten() {
+            let fixture = Arc::clone(&self.fixture);
+            thread::spawn(move || {
+                // A client hanging up early is not the server's problem.
+                let _ = handle_connection(stream, &fixture);
+            });
+        }
+    }
+}
+
+/// Answer the one request of `stream`, then close it.
+fn handle_connection(stream: TcpStream, fixture: &Fixture) -> io::Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    let mut content_length = 0;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().unwrap_or(0);
+            }
+        }
+    }
+    let mut body = Vec::new();
+    reader.take(content_length).read_to_end(&mut body)?;
+
+    let mut request_line = request_line.split_whitespace();
+    let method = request_line.next().unwrap_or("GET");
+    let target = request_line.next().unwrap_or("/");
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query: HashMap<&str, &str> = query
+        .split('&')
+        .filter_map(|param| param.split_once('='))
+        .collect();
+
+    let dns_message = match (method, path) {
+        ("GET", "/dns-query") => query
+            .get("dns")
+            .and_then(|dns| URL_SAFE_NO_PAD.decode(dns).ok()),
+        ("POST", "/dns-query") => Some(body),
+        _ => None,
+    };
+    let (status, content_type, body) = match (path, query.get("name"), query.get("type")) {
+        ("/resolve", Some(name), Some(record_type)) => (
+            "200 OK",
+            JSON_CONTENT_TYPE,
+            fixture
+                .resolve(&percent_decode(name), record_type)
+                .to_string()
+                .into_bytes(),
+        ),
+        ("/resolve", _, _) => ("400 Bad Request", JSON_CONTENT_TYPE, Vec::new()),
+        ("/dns-query", _, _) => match dns_message.and_then(|query| fixture.resolve_message(&query))
+        {
+            Some(response) => ("200 OK", DNS_MESSAGE_CONTENT_TYPE, response),
+            None => ("400 Bad Request", DNS_MESSAGE_CONTENT_TYPE, Vec::new()),
+        },
+        (path, _, _) => match fixture.ip_ranges.get(path) {
+            Some(ip_ranges) => (
+                "200 OK",
+                JSON_CONTENT_TYPE,
+                ip_ranges.to_string().into_bytes(),
+            ),
+            None => ("404 Not Found", JSON_CONTENT_TYPE, Vec::new()),
+        },
+    };
+
+    let mut stream = stream;
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+    stream.write_all(&body)?;
+    stream.flush()
+}
+
+/// Decode the `%XX` escapes of a query string parameter.
+fn percent_decode(value: &str) -> String {
+    let mut decoded = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(byte) = bytes.next() {
+        let escaped = (byte == b'%')
+            .then(|| {
+                let hex = [bytes.clone().next()?, bytes.clone().nth(1)?];
+                u8::from_str_radix(std::str::from_utf8(&hex).ok()?, 16).ok()
+            })
+            .flatten();
+        match escaped {
+            Some(escaped) => {
+                decoded.push(escaped);
+                bytes.nth(1);
+            }
+            None => decoded.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The lowercase form of a domain name, without its trailing dot.
+fn normalize_name(name: &str) -> String {
+    name.trim_end_matches('.').to_ascii_lowercase()
+}
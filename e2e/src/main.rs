@@ -0,0 +1,24 @@
+//! Run the fake DNS-over-HTTPS server, so that `fastly compute serve` works offline:
+//!
+//! ```sh
+//! cargo run -p e2e --bin fake-doh --target x86_64-unknown-linux-gnu -- [FIXTURE] [ADDR]
+//! ```
+//!
+//! The fixture is `e2e/fixtures/dns.json` by default, and the address the one the local
+//! server backends in `fastly.toml` point at.
+
+use e2e::{FakeDoh, ADDR, FIXTURE};
+use std::path::PathBuf;
+
+fn main() -> std::io::Result<()> {
+    let mut args = std::env::args().skip(1);
+    let fixture = args
+        .next()
+        .map_or_else(|| PathBuf::from(FIXTURE), PathBuf::from);
+    let addr = args.next().unwrap_or_else(|| ADDR.to_string());
+
+    let fake_doh = FakeDoh::bind(&addr, &fixture)?;
+    println!("Serving {} on http://{}", fixture.display(), addr);
+    fake_doh.serve();
+    Ok(())
+}
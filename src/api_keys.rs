@@ -0,0 +1,138 @@
+use crate::outcome::Outcome;
+
+/// Whether lookup requests are authenticated by API key, as configured by `api_keys`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ApiKeyMode {
+    /// `off`: no request is authenticated.
+    Off,
+    /// `optional`: a request without a key goes through, one with a key is authenticated.
+    Optional,
+    /// `required`: every request must carry a key.
+    Required,
+}
+
+impl ApiKeyMode {
+    /// The mode set by the `api_keys` value, `off` if there is none.
+    ///
+    /// Any value but `off` and `optional` requires a key, so that a typo never opens the API.
+    pub fn from_config(api_keys: Option<&str>) -> ApiKeyMode {
+        match api_keys.map(str::trim) {
+            None | Some("off") => ApiKeyMode::Off,
+            Some("optional") => ApiKeyMode::Optional,
+            Some(_) => ApiKeyMode::Required,
+        }
+    }
+
+    /// The API key to authenticate a client request by, given the key it presents, if any.
+    ///
+    /// Returns `None` if the request goes through without being authenticated, and
+    /// [`Outcome::Unauthorized`] if a required key is missing.
+    pub fn key_to_check(self, presented_api_key: Option<&str>) -> Result<Option<&str>, Outcome> {
+        match (self, presented_api_key) {
+            (ApiKeyMode::Off, _) => Ok(None),
+            (ApiKeyMode::Required, None) => Err(Outcome::Unauthorized),
+            (_, presented_api_key) => Ok(presented_api_key),
+        }
+    }
+}
+
+/// The API key a client request presents, given the values of its API key header and of its
+/// `Authorization` header, if any: the API key header wins over a bearer token.
+/// Blank keys are ignored.
+pub fn presented_api_key<'a>(
+    api_key_header: Option<&'a str>,
+    authorization: Option<&'a str>,
+) -> Option<&'a str> {
+    let bearer_token = authorization.and_then(|authorization| {
+        let (scheme, token) = authorization.trim().split_once(' ')?;
+        scheme.eq_ignore_ascii_case("bearer").then_some(token)
+    });
+    [api_key_header, bearer_token]
+        .into_iter()
+        .flatten()
+        .map(str::trim)
+        .find(|api_key| !api_key.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_mode() {
+        assert_eq!(ApiKeyMode::from_config(None), ApiKeyMode::Off);
+        assert_eq!(ApiKeyMode::from_config(Some("off")), ApiKeyMode::Off);
+        assert_eq!(
+            ApiKeyMode::from_config(Some("optional")),
+            ApiKeyMode::Optional
+        );
+        assert_eq!(
+            ApiKeyMode::from_config(Some(" required ")),
+            ApiKeyMode::Required
+        );
+    }
+
+    #[test]
+    fn requires_a_key_for_unknown_modes() {
+        for api_keys in ["requierd", "Optional", "on", "no"] {
+            assert_eq!(
+                ApiKeyMode::from_config(Some(api_keys)),
+                ApiKeyMode::Required,
+                "{:?}",
+                api_keys
+            );
+        }
+    }
+
+    #[test]
+    fn checks_keys_by_mode() {
+        assert_eq!(ApiKeyMode::Off.key_to_check(None), Ok(None));
+        assert_eq!(ApiKeyMode::Off.key_to_check(Some("key")), Ok(None));
+
+        assert_eq!(ApiKeyMode::Optional.key_to_check(None), Ok(None));
+        assert_eq!(
+            ApiKeyMode::Optional.key_to_check(Some("key")),
+            Ok(Some("key"))
+        );
+
+        assert_eq!(
+            ApiKeyMode::Required.key_to_check(None),
+            Err(Outcome::Unauthorized)
+        );
+        assert_eq!(
+            ApiKeyMode::Required.key_to_check(Some("key")),
+            Ok(Some("key"))
+        );
+    }
+
+    #[test]
+    fn reads_the_api_key_header() {
+        assert_eq!(presented_api_key(Some("key"), None), Some("key"));
+        assert_eq!(presented_api_key(Some("  key \t"), None), Some("key"));
+        assert_eq!(presented_api_key(None, None), None);
+    }
+
+    #[test]
+    fn reads_a_bearer_token() {
+        assert_eq!(presented_api_key(None, Some("Bearer key")), Some("key"));
+        assert_eq!(presented_api_key(None, Some("bearer  key ")), Some("key"));
+        assert_eq!(presented_api_key(None, Some("BEARER key")), Some("key"));
+        assert_eq!(presented_api_key(None, Some("Basic a2V5Og==")), None);
+        assert_eq!(presented_api_key(None, Some("Bearer")), None);
+        assert_eq!(presented_api_key(None, Some("key")), None);
+    }
+
+    #[test]
+    fn prefers_the_api_key_header() {
+        assert_eq!(
+            presented_api_key(Some("header-key"), Some("Bearer bearer-key")),
+            Some("header-key")
+        );
+        // A blank header does not hide a bearer token.
+        assert_eq!(
+            presented_api_key(Some(" "), Some("Bearer bearer-key")),
+            Some("bearer-key")
+        );
+        assert_eq!(presented_api_key(Some(""), Some("Bearer ")), None);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::config::config;
+use crawler_verification::api_keys::presented_api_key;
+use crawler_verification::outcome::Outcome;
+use fastly::http::header;
+use fastly::{KVStore, Request};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// The name of the KV Store holding the API keys.
+const KV_STORE: &str = "crawler_verification";
+
+/// The prefix of the KV Store keys of the API keys. An API key is stored under the
+/// lowercase hex SHA-256 of the key, e.g. `api_keys/9f86d081…`, so the store never holds
+/// a usable key.
+const API_KEY_PREFIX: &str = "api_keys/";
+
+/// What a client request asks for, which its API key must grant.
+#[derive(Clone, Copy)]
+pub enum Scope {
+    /// `GET /verify` and `GET /v2/verify`.
+    Verify,
+    /// `POST /verify/batch`.
+    Batch,
+}
+
+impl Scope {
+    /// The name of the scope in the `scopes` of an API key.
+    fn name(self) -> &'static str {
+        match self {
+            Scope::Verify => "verify",
+            Scope::Batch => "batch",
+        }
+    }
+}
+
+/// An API key, as stored in the KV Store as JSON, e.g.
+/// `{"id": "partner-team", "scopes": ["verify", "batch"], "rate_limit": 20}`.
+#[derive(Deserialize)]
+pub struct ApiKey {
+    /// The name of the key, which is logged instead of the key itself.
+    pub id: String,
+    /// The names of the [`Scope`]s the key grants.
+    #[serde(default)]
+    scopes: Vec<String>,
+    /// The most lookups per second the key may ask for, averaged over 10 seconds,
+    /// instead of the configured `rate_limit_api_key`.
+    #[serde(default)]
+    pub rate_limit: Option<u32>,
+}
+
+/// The API key the client request was authenticated with, if any.
+static AUTHENTICATED: OnceLock<ApiKey> = OnceLock::new();
+
+/// Authenticate a client request asking for `scope` by the API key it carries, in its API key
+/// header or as a bearer token, as configured by `api_keys`.
+///
+/// Returns the API key, if the request was authenticated, or [`Outcome::Unauthorized`] if
+/// a required key is missing or the key is unknown, and [`Outcome::Forbidden`] if the key
+/// does not grant `scope`.
+pub fn authenticate(req: &Request, scope: Scope) -> Result<Option<&'static ApiKey>, Outcome> {
+    let header_str = |name: &str| req.get_header(name).and_then(|value| value.to_str().ok());
+    let presented_api_key = presented_api_key(
+        header_str(&config().api_key_header),
+        header_str(header::AUTHORIZATION.as_str()),
+    );
+    let presented_api_key = match config().api_keys.key_to_check(presented_api_key)? {
+        Some(presented_api_key) => presented_api_key,
+        None => return Ok(None),
+    };
+
+    let api_key = lookup(presented_api_key).ok_or(Outcome::Unauthorized)?;
+    if !api_key.scopes.iter().any(|name| name == scope.name()) {
+        return Err(Outcome::Forbidden);
+    }
+    Ok(Some(AUTHENTICATED.get_or_init(|| api_key)))
+}
+
+/// The id of the API key the client request was authenticated with, if any.
+pub fn key_id() -> Option<&'static str> {
+    AUTHENTICATED.get().map(|api_key| api_key.id.as_str())
+}
+
+/// The stored API key matching `presented_api_key`, if there is one and it can be read.
+fn lookup(presented_api_key: &str) -> Option<ApiKey> {
+    let hash: String = Sha256::digest(presented_api_key.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    let api_key = KVStore::open(KV_STORE)
+        .ok()??
+        .lookup_str(&format!("{}{}", API_KEY_PREFIX, hash))
+        .ok()??;
+    serde_json::from_str(&api_key).ok()
+}
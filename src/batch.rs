@@ -0,0 +1,396 @@
+use crate::auth::{self, Scope};
+use crate::config::config;
+use crate::dns::{self, DnsError, DnsResponse, RecordType};
+use crate::{
+    cache, candidate_crawlers, ip_range_lists, json_response, log_verification, override_outcome,
+    verify_cache_key, CacheStatus,
+};
+use crate::{latency_budget, metrics, rate_limit};
+use crawler_verification::batch_entries::parse_entries;
+use crawler_verification::crawlers::{Crawler, DEFAULT_CRAWLER};
+use crawler_verification::outcome::Outcome;
+use crawler_verification::verify::{
+    decide_ptr, forward_record_type, in_published_ip_range, ForwardLookup, PtrDecision,
+};
+use fastly::http::request::PendingRequest;
+use fastly::http::StatusCode;
+use fastly::{Request, Response};
+use serde_json::Value;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::time::Instant;
+
+/// The most DNS requests in flight at once for a batch client request.
+const MAX_CONCURRENT_DNS_REQUESTS: usize = 32;
+
+/// The verification of one IP of a batch.
+struct Lookup {
+    /// The IP as given in the client request.
+    entry: String,
+    ip: Option<IpAddr>,
+    outcome: Option<Outcome>,
+    /// The PTR record waiting for its forward lookup, if any.
+    forward_lookup: Option<ForwardLookup>,
+    /// The resolver of the last DNS answer, if any.
+    resolver: Option<&'static str>,
+    /// How long ago the outcome was cached, if it was served from the edge cache.
+    cache_age: Option<u64>,
+}
+
+/// What a DNS request of a batch was sent for.
+#[derive(Clone, Copy)]
+enum Stage {
+    Ptr,
+    Forward,
+}
+
+/// Verify every IP in the body of a `POST /verify/batch` client request, either a JSON array
+/// of strings or newline-delimited text. Repeated IPs are only verified and reported once.
+///
+/// The batch counts against the rate limits of the caller as one lookup per distinct IP,
+/// and at least one. The caller is authenticated before the body is read.
+pub fn handle_batch_request(mut req: Request) -> Result<Response, Outcome> {
+    rate_limit::check_ip(&req, 1)?;
+    let api_key = auth::authenticate(&req, Scope::Batch)?;
+
+    let qs_params: HashMap<String, String> =
+        req.get_query().map_err(|_| Outcome::InvalidQueryString)?;
+    let crawler_name = qs_params
+        .get("crawler")
+        .map(String::as_str)
+        .unwrap_or(DEFAULT_CRAWLER);
+    let (candidates, auto_identify) = candidate_crawlers(crawler_name)?;
+
+    let body = String::from_utf8(req.take_body_bytes()).map_err(|_| Outcome::InvalidBatch {
+        reason: "The batch is not valid UTF-8".to_string(),
+    })?;
+    let entries: Vec<(String, Option<IpAddr>)> = parse_entries(&body)
+        .map_err(|reason| Outcome::InvalidBatch { reason })?
+        .into_iter()
+        .map(|entry| {
+            let ip = entry.parse::<IpAddr>().ok();
+            (entry, ip)
+        })
+        .collect();
+    let distinct_ips = entries.iter().filter(|(_, ip)| ip.is_some()).count() as u32;
+    // The client IP was already charged one lookup, before the request was authenticated.
+    rate_limit::check_ip(&req, distinct_ips.saturating_sub(1))?;
+    rate_limit::check_api_key(api_key, distinct_ips.max(1))?;
+
+    let started = Instant::now();
+    let mut lookups: Vec<Lookup> = entries
+        .into_iter()
+        .map(|(entry, ip)| {
+            let (outcome, cache_age) = match ip {
+                Some(ip) => match override_outcome(ip) {
+                    Some(outcome) => (Some(outcome), None),
+                    None => {
+                        match cache::lookup(&verify_cache_key(ip, &candidates, auto_identify)) {
+                            Some((outcome, age)) => (Some(outcome), Some(age)),
+                            None => (None, None),
+                        }
+                    }
+                },
+                None => (
+                    Some(Outcome::InvalidBatch {
+                        reason: format!("Invalid IP address {}", entry),
+                    }),
+                    None,
+                ),
+            };
+            Lookup {
+                entry,
+                ip,
+                outcome,
+                forward_lookup: None,
+                resolver: None,
+                cache_age,
+            }
+        })
+        .collect();
+
+    verify_lookups(&mut lookups, &candidates, auto_identify);
+    let latency = started.elapsed();
+
+    let results: Vec<Value> = lookups
+        .into_iter()
+        .map(|lookup| {
+            let outcome = lookup.outcome.unwrap_or(Outcome::GoogleDnsFailed);
+            if let Some(ip) = lookup.ip {
+                let cache_status = match lookup.cache_age {
+                    Some(age) => CacheStatus::Hit { age },
+                    None => CacheStatus::Miss {
+                        resolver: lookup.resolver,
+                    },
+                };
+                log_verification(ip, &outcome, &cache_status, latency);
+            }
+            let (_, mut result_json) = outcome.into_status_and_json();
+            result_json["ip"] = lookup.entry.into();
+            if let Some(resolver) = lookup.resolver {
+                result_json["resolver"] = resolver.into();
+            }
+            result_json
+        })
+        .collect();
+
+    Ok(json_response(
+        StatusCode::OK,
+        &serde_json::json!({
+            "results": results,
+            "dns_attempts": dns::attempts(),
+        }),
+    ))
+}
+
+/// Verify every lookup that does not have an outcome yet, sending their DNS requests concurrently.
+fn verify_lookups(lookups: &mut [Lookup], candidates: &[&'static Crawler], auto_identify: bool) {
+    let unverified: Vec<usize> = (0..lookups.len())
+        .filter(|index| lookups[*index].outcome.is_none())
+        .collect();
+    if unverified.is_empty() {
+        return;
+    }
+
+    let published_ip_ranges = ip_range_lists::fetch_ip_ranges(candidates);
+    for lookup in lookups.iter_mut().filter(|lookup| lookup.outcome.is_none()) {
+        lookup.outcome = lookup
+            .ip
+            .and_then(|ip| in_published_ip_range(&published_ip_ranges, ip));
+    }
+
+    let mut dns_requests = DnsRequests::default();
+    for (index, lookup) in lookups.iter_mut().enumerate() {
+        if let (None, Some(ip)) = (&lookup.outcome, lookup.ip) {
+            let name = dns::reverse_lookup_name(ip);
+            if let Err(error) = dns_requests.queue(&name, RecordType::Ptr, Stage::Ptr, index) {
+                lookup.outcome = Some(error.into());
+            }
+        }
+    }
+
+    while let Some((stage, indexes, dns_response)) = dns_requests.next() {
+        for index in indexes {
+            let lookup = &mut lookups[index];
+            let ip = match lookup.ip {
+                Some(ip) => ip,
+                None => continue,
+            };
+            let dns_response = match &dns_response {
+                Ok(dns_response) => {
+                    lookup.resolver = dns_response.resolver;
+                    dns_response
+                }
+                Err(error) => {
+                    lookup.outcome = Some((*error).into());
+                    continue;
+                }
+            };
+
+            let decision = match (stage, lookup.forward_lookup.take()) {
+                (Stage::Ptr, _) => decide_ptr(
+                    dns_response,
+                    candidates,
+                    auto_identify,
+                    config().ptr_suffixes(),
+                ),
+                (Stage::Forward, Some(forward_lookup)) => forward_lookup.decide(dns_response, ip),
+                (Stage::Forward, None) => continue,
+            };
+            match decision {
+                PtrDecision::Decided(outcome) => lookup.outcome = Some(outcome),
+                PtrDecision::NeedsForwardLookup(forward_lookup) => {
+                    match dns_requests.queue(
+                        &forward_lookup.ptr_record,
+                        forward_record_type(ip),
+                        Stage::Forward,
+                        index,
+                    ) {
+                        Ok(()) => lookup.forward_lookup = Some(forward_lookup),
+                        Err(error) => lookup.outcome = Some(error.into()),
+                    }
+                }
+            }
+        }
+    }
+
+    for lookup in unverified.into_iter().map(|index| &lookups[index]) {
+        if let (Some(outcome), Some(ip)) = (&lookup.outcome, lookup.ip) {
+            cache::insert(&verify_cache_key(ip, candidates, auto_identify), outcome);
+        }
+    }
+}
+
+/// The DNS requests of a batch, at most [`MAX_CONCURRENT_DNS_REQUESTS`] of which are in
+/// flight at once. Identical requests are only sent once, on behalf of every lookup needing them.
+///
+/// A request that failed transiently is retried, then sent to the next of the configured resolvers.
+/// Since a batch already sends many requests at once, requests are not raced against several
+/// resolvers, and retries are queued behind the other requests rather than backed off.
+#[derive(Default)]
+struct DnsRequests {
+    queued: VecDeque<Request>,
+    in_flight: Vec<PendingRequest>,
+    /// The query each request was sent for, by URL.
+    waiting: HashMap<String, Query>,
+    /// The URLs of the requests given up on when the latency budget ran out.
+    timed_out: Vec<String>,
+}
+
+/// A DNS query of a batch, and the lookups waiting on its answer.
+struct Query {
+    name: String,
+    record_type: RecordType,
+    /// The index of the resolver the query is sent to among the configured resolvers.
+    resolver: usize,
+    /// How many times the query was retried with that resolver.
+    retries: u32,
+    /// When the query was last sent.
+    sent: Instant,
+    stage: Stage,
+    indexes: Vec<usize>,
+}
+
+impl DnsRequests {
+    /// Queue a request for the `record_type` records of `name` on behalf of the lookup at `index`.
+    fn queue(
+        &mut self,
+        name: &str,
+        record_type: RecordType,
+        stage: Stage,
+        index: usize,
+    ) -> Result<(), DnsError> {
+        // Requests are identified by their URL, so they are always sent with GET.
+        let dns_request = config().resolvers()[0].get_request(name, record_type)?;
+        let query = Query {
+            name: name.to_string(),
+            record_type,
+            resolver: 0,
+            retries: 0,
+            sent: Instant::now(),
+            stage,
+            indexes: vec![index],
+        };
+        self.send(dns_request, query);
+        Ok(())
+    }
+
+    /// Queue `dns_request` for `query`, unless an identical request is already waiting,
+    /// in which case the lookups of `query` wait on that one instead.
+    fn send(&mut self, dns_request: Request, query: Query) {
+        match self.waiting.get_mut(dns_request.get_url_str()) {
+            Some(waiting_query) => waiting_query.indexes.extend(query.indexes),
+            None => {
+                let url = dns_request.get_url_str().to_string();
+                self.waiting.insert(url, query);
+                self.queued.push_back(dns_request);
+            }
+        }
+    }
+
+    /// Wait for the next query to be answered, returning the stage and lookups it was sent for,
+    /// along with its parsed response.
+    fn next(&mut self) -> Option<(Stage, Vec<usize>, Result<DnsResponse, DnsError>)> {
+        loop {
+            while let Some(url) = self.timed_out.pop() {
+                if let Some(completed) = self.complete(&url, Err(DnsError::Timeout)) {
+                    return Some(completed);
+                }
+            }
+
+            while self.in_flight.len() < MAX_CONCURRENT_DNS_REQUESTS {
+                let dns_request = match self.queued.pop_front() {
+                    Some(dns_request) => dns_request,
+                    None => break,
+                };
+                let url = dns_request.get_url_str().to_string();
+                let resolver = match self.waiting.get_mut(&url) {
+                    Some(query) => {
+                        query.sent = Instant::now();
+                        config().resolvers()[query.resolver]
+                    }
+                    None => continue,
+                };
+                match resolver.send_async(dns_request) {
+                    Ok(pending_request) => self.in_flight.push(pending_request),
+                    Err(error) => {
+                        if let Some(completed) = self.complete(&url, Err(error)) {
+                            return Some(completed);
+                        }
+                    }
+                }
+            }
+            if self.in_flight.is_empty() {
+                return None;
+            }
+
+            let in_flight = std::mem::take(&mut self.in_flight);
+            let (beresp, in_flight) = match latency_budget::select_within_budget(in_flight) {
+                Some(selected) => selected,
+                None => {
+                    // Every query still waiting times out, and is not sent to any other resolver.
+                    self.timed_out = self.waiting.keys().cloned().collect();
+                    continue;
+                }
+            };
+            self.in_flight = in_flight;
+            let completed = match beresp {
+                Ok(beresp) => match beresp.get_backend_request() {
+                    Some(dns_request) => {
+                        let url = dns_request.get_url_str().to_string();
+                        self.complete(&url, Ok(beresp))
+                    }
+                    None => None,
+                },
+                Err(error) => {
+                    let dns_error = dns::send_error(&error);
+                    self.complete(error.into_sent_req().get_url_str(), Err(dns_error))
+                }
+            };
+            if completed.is_some() {
+                return completed;
+            }
+        }
+    }
+
+    /// Hand the response to the request for `url` back to the lookups waiting on it, or,
+    /// if the request failed, send the query again and return `None`: to the same resolver
+    /// if the failure is transient and it has retries left, to the next resolver otherwise.
+    fn complete(
+        &mut self,
+        url: &str,
+        beresp: Result<Response, DnsError>,
+    ) -> Option<(Stage, Vec<usize>, Result<DnsResponse, DnsError>)> {
+        let mut query = self.waiting.remove(url)?;
+        let resolver = config().resolvers()[query.resolver];
+        let dns_response = beresp.and_then(|beresp| resolver.parse_response(beresp));
+        metrics::record_dns_request(resolver.name, &dns_response, query.sent.elapsed());
+
+        if let Err(error) = dns_response {
+            if error.is_retryable() && query.retries < config().dns_retries {
+                if let Ok(dns_request) = resolver.get_request(&query.name, query.record_type) {
+                    query.retries += 1;
+                    self.send(dns_request, query);
+                    return None;
+                }
+            }
+
+            let next_request =
+                config()
+                    .resolvers()
+                    .get(query.resolver + 1)
+                    .and_then(|next_resolver| {
+                        next_resolver
+                            .get_request(&query.name, query.record_type)
+                            .ok()
+                    });
+            if let Some(dns_request) = next_request {
+                query.resolver += 1;
+                query.retries = 0;
+                self.send(dns_request, query);
+                return None;
+            }
+        }
+        Some((query.stage, query.indexes, dns_response))
+    }
+}
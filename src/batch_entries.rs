@@ -0,0 +1,140 @@
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+/// The most IPs a single batch client request may contain.
+pub const MAX_BATCH_SIZE: usize = 1000;
+
+/// Split the body of a batch client request, either a JSON array of strings or
+/// newline-delimited text, into its distinct entries, trimmed and in the order they first
+/// appear. Blank entries are dropped, and entries spelling the same IP differently, e.g.
+/// `2001:db8::1` and `2001:DB8:0::1`, are one entry.
+///
+/// Returns why the body is not a valid batch, e.g. if it has more than [`MAX_BATCH_SIZE`]
+/// distinct entries.
+pub fn parse_entries(body: &str) -> Result<Vec<String>, String> {
+    let entries: Vec<String> = if body.trim_start().starts_with('[') {
+        serde_json::from_str(body).map_err(|_| "Invalid JSON array of IPs".to_string())?
+    } else {
+        body.lines().map(str::to_string).collect()
+    };
+
+    let mut seen = HashSet::new();
+    let entries: Vec<String> = entries
+        .into_iter()
+        .map(|entry| entry.trim().to_string())
+        .filter(|entry| {
+            // Entries that are not IPs are reported as such, once per spelling.
+            let key = entry.parse::<IpAddr>().map_err(|_| entry.clone());
+            !entry.is_empty() && seen.insert(key)
+        })
+        .collect();
+    if entries.len() > MAX_BATCH_SIZE {
+        return Err(format!(
+            "A batch may contain at most {} IPs",
+            MAX_BATCH_SIZE
+        ));
+    }
+    Ok(entries)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_json_array() {
+        assert_eq!(
+            parse_entries(r#" ["66.249.66.1", " 157.55.39.1 ", "2001:db8::1"]"#),
+            Ok(vec![
+                "66.249.66.1".to_string(),
+                "157.55.39.1".to_string(),
+                "2001:db8::1".to_string()
+            ])
+        );
+        assert_eq!(parse_entries("[]"), Ok(vec![]));
+    }
+
+    #[test]
+    fn parses_newline_delimited_text() {
+        assert_eq!(
+            parse_entries("66.249.66.1\r\n  157.55.39.1\t\n\n   \nnot-an-ip\n"),
+            Ok(vec![
+                "66.249.66.1".to_string(),
+                "157.55.39.1".to_string(),
+                "not-an-ip".to_string()
+            ])
+        );
+        assert_eq!(parse_entries(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn keeps_the_first_of_repeated_entries() {
+        assert_eq!(
+            parse_entries("192.0.2.2\n192.0.2.1\n 192.0.2.2\n192.0.2.3\n192.0.2.1"),
+            Ok(vec![
+                "192.0.2.2".to_string(),
+                "192.0.2.1".to_string(),
+                "192.0.2.3".to_string()
+            ])
+        );
+        assert_eq!(
+            parse_entries(r#"["192.0.2.1", "", "192.0.2.1"]"#),
+            Ok(vec!["192.0.2.1".to_string()])
+        );
+    }
+
+    #[test]
+    fn keeps_the_first_spelling_of_repeated_ips() {
+        assert_eq!(
+            parse_entries("2001:db8::1\n2001:DB8:0::1\n2001:0db8:0000::0001\n192.0.2.1"),
+            Ok(vec!["2001:db8::1".to_string(), "192.0.2.1".to_string()])
+        );
+        assert_eq!(
+            parse_entries("not-an-ip\nNOT-AN-IP\nnot-an-ip"),
+            Ok(vec!["not-an-ip".to_string(), "NOT-AN-IP".to_string()])
+        );
+    }
+
+    #[test]
+    fn rejects_a_json_array_of_anything_but_strings() {
+        for body in [
+            r#"["192.0.2.1", 1]"#,
+            r#"["192.0.2.1", null]"#,
+            r#"[["192.0.2.1"]]"#,
+            r#"["192.0.2.1""#,
+        ] {
+            assert_eq!(
+                parse_entries(body),
+                Err("Invalid JSON array of IPs".to_string()),
+                "{}",
+                body
+            );
+        }
+    }
+
+    #[test]
+    fn caps_the_distinct_entries() {
+        let ips: Vec<String> = (0..MAX_BATCH_SIZE + 1)
+            .map(|index| format!("10.0.{}.{}", index / 256, index % 256))
+            .collect();
+        assert_eq!(
+            parse_entries(&ips[..MAX_BATCH_SIZE].join("\n")).map(|entries| entries.len()),
+            Ok(MAX_BATCH_SIZE)
+        );
+        assert_eq!(
+            parse_entries(&ips.join("\n")),
+            Err(format!(
+                "A batch may contain at most {} IPs",
+                MAX_BATCH_SIZE
+            ))
+        );
+
+        // Repeated entries are only counted once.
+        let mut repeated = ips[..MAX_BATCH_SIZE].to_vec();
+        repeated.extend_from_slice(&ips[..10]);
+        assert_eq!(
+            parse_entries(&repeated.join("\n")).map(|entries| entries.len()),
+            Ok(MAX_BATCH_SIZE)
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use crawler_verification::crawlers::Crawler;
+use crawler_verification::outcome::Outcome;
+use fastly::cache::simple::{self, CacheEntry};
+use serde_json::Value;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long, in seconds, an IP found in a crawler's published IP ranges is cached.
+const PUBLISHED_IP_RANGE_TTL: u32 = 3600;
+
+/// How long, in seconds, an IP whose PTR record is not a crawler domain,
+/// or does not resolve back to the IP, is cached.
+const NOT_CRAWLER_TTL: u32 = 3600;
+
+/// How long, in seconds, an IP without a PTR record, or without a reverse DNS name at all, is cached.
+/// Kept short, since a missing PTR record is often a transient DNS issue.
+const NO_PTR_ANSWER_TTL: u32 = 300;
+
+/// Look up the cached outcome of verifying an IP, along with its age in seconds.
+pub fn lookup(key: &str) -> Option<(Outcome, u64)> {
+    let body = simple::get(key.to_string()).ok()??;
+    let entry: Value = serde_json::from_str(&body.into_string()).ok()?;
+    let age = now().saturating_sub(entry["cached_at"].as_u64()?);
+    Some((decode(&entry)?, age))
+}
+
+/// Cache the outcome of verifying an IP. Outcomes that did not reach a decision are not cached.
+pub fn insert(key: &str, outcome: &Outcome) {
+    let ttl = match ttl(outcome) {
+        Some(ttl) if ttl > 0 => ttl,
+        _ => return,
+    };
+    let mut entry = match encode(outcome) {
+        Some(entry) => entry,
+        None => return,
+    };
+    entry["cached_at"] = now().into();
+
+    // Caching is best-effort: if it fails, the next request just looks the IP up again.
+    let _ = simple::get_or_set_with(key.to_string(), || {
+        Ok(CacheEntry {
+            value: entry.to_string().into(),
+            ttl: Duration::from_secs(ttl.into()),
+        })
+    });
+}
+
+/// How long, in seconds, an outcome may be cached.
+pub fn ttl(outcome: &Outcome) -> Option<u32> {
+    use Outcome::*;
+    match outcome {
+        InPublishedIpRange { .. } => Some(PUBLISHED_IP_RANGE_TTL),
+        IsCrawler { ttl, .. } => Some(*ttl),
+        NotCrawler { .. } | ForwardLookupMismatch { .. } => Some(NOT_CRAWLER_TTL),
+        NoPtrAnswer | PtrNxDomain => Some(NO_PTR_ANSWER_TTL),
+        _ => None,
+    }
+}
+
+/// Serialize an outcome into a cache entry.
+fn encode(outcome: &Outcome) -> Option<Value> {
+    use Outcome::*;
+    let entry = match outcome {
+        InPublishedIpRange {
+            crawler,
+            list,
+            prefix,
+        } => serde_json::json!({
+            "outcome": "in_published_ip_range",
+            "crawler": crawler,
+            "list": list,
+            "prefix": prefix,
+        }),
+        IsCrawler {
+            crawler,
+            ptr_record,
+            ptr_records,
+            ttl,
+        } => serde_json::json!({
+            "outcome": "is_crawler",
+            "crawler": crawler,
+            "ptr_record": ptr_record,
+            "ptr_records": ptr_records,
+            "ttl": ttl,
+        }),
+        NotCrawler {
+            crawler,
+            ptr_record,
+            ptr_records,
+        } => serde_json::json!({
+            "outcome": "not_crawler",
+            "crawler": crawler,
+            "ptr_record": ptr_record,
+            "ptr_records": ptr_records,
+        }),
+        ForwardLookupMismatch {
+            crawler,
+            ptr_record,
+            ptr_records,
+        } => serde_json::json!({
+            "outcome": "forward_lookup_mismatch",
+            "crawler": crawler,
+            "ptr_record": ptr_record,
+            "ptr_records": ptr_records,
+        }),
+        NoPtrAnswer => serde_json::json!({
+            "outcome": "no_ptr_answer",
+        }),
+        PtrNxDomain => serde_json::json!({
+            "outcome": "ptr_nxdomain",
+        }),
+        _ => return None,
+    };
+    Some(entry)
+}
+
+/// Deserialize an outcome from a cache entry.
+///
+/// Returns `None` if the entry refers to a crawler or IP range list that no longer exists.
+fn decode(entry: &Value) -> Option<Outcome> {
+    let crawler = entry["crawler"].as_str().and_then(Crawler::find);
+    let ptr_record = entry["ptr_record"].as_str().map(str::to_string);
+    // Entries cached before every PTR record was kept only hold the one decided on.
+    let ptr_records = match entry["ptr_records"].as_array() {
+        Some(ptr_records) => ptr_records
+            .iter()
+            .map(|ptr_record| ptr_record.as_str().map(str::to_string))
+            .collect::<Option<Vec<_>>>()?,
+        None => ptr_record.iter().cloned().collect(),
+    };
+
+    let outcome = match entry["outcome"].as_str()? {
+        "in_published_ip_range" => {
+            let crawler = crawler?;
+            let list = entry["list"].as_str()?;
+            Outcome::InPublishedIpRange {
+                crawler: crawler.name,
+                list: crawler
+                    .ip_range_lists
+                    .iter()
+                    .find(|ip_range_list| ip_range_list.name == list)?
+                    .name,
+                prefix: entry["prefix"].as_str()?.to_string(),
+            }
+        }
+        "is_crawler" => Outcome::IsCrawler {
+            crawler: crawler?.name,
+            ptr_record: ptr_record?,
+            ptr_records,
+            ttl: entry["ttl"].as_u64()?.try_into().ok()?,
+        },
+        "not_crawler" => Outcome::NotCrawler {
+            crawler: crawler.map(|crawler| crawler.name),
+            ptr_record: ptr_record?,
+            ptr_records,
+        },
+        "forward_lookup_mismatch" => Outcome::ForwardLookupMismatch {
+            crawler: crawler?.name,
+            ptr_record: ptr_record?,
+            ptr_records,
+        },
+        "no_ptr_answer" => Outcome::NoPtrAnswer,
+        "ptr_nxdomain" => Outcome::PtrNxDomain,
+        _ => return None,
+    };
+    Some(outcome)
+}
+
+/// The current time, in seconds since the Unix epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,67 @@
+use crate::config::config;
+use fastly::Request;
+use std::net::IpAddr;
+
+/// Where the IP verified by a lookup request came from.
+#[derive(Clone, Copy)]
+pub enum IpSource {
+    /// The `?ip=` query parameter.
+    Query,
+    /// The IP of the connecting client.
+    Client,
+    /// The configured client IP header, set by a trusted proxy.
+    Header,
+}
+
+impl IpSource {
+    /// The name of the source, as reported in lookup responses.
+    pub fn name(self) -> &'static str {
+        match self {
+            IpSource::Query => "query",
+            IpSource::Client => "client",
+            IpSource::Header => "header",
+        }
+    }
+}
+
+/// The IP of the client that sent `req`, and where it came from.
+///
+/// If the connecting client is a trusted proxy, the configured client IP header is read from
+/// right to left, skipping the trusted proxies that appended to it, e.g. `X-Forwarded-For:
+/// <client>, <trusted proxy>`. The connecting client's IP is used if there is no such header,
+/// or it holds anything but IPs.
+pub fn client_ip(req: &Request) -> Option<(IpAddr, IpSource)> {
+    let connecting_ip = req.get_client_ip_addr()?;
+    let config = config();
+
+    let header = match &config.client_ip_header {
+        Some(header) if config.is_trusted_proxy(connecting_ip) => header,
+        _ => return Some((connecting_ip, IpSource::Client)),
+    };
+    // A value that is not valid UTF-8 is as invalid as one that is not a list of IPs.
+    let header_ips = req
+        .get_header_all(header)
+        .map(|value| {
+            value
+                .to_str()
+                .ok()?
+                .split(',')
+                .map(|ip| ip.trim().parse::<IpAddr>().ok())
+                .collect::<Option<Vec<_>>>()
+        })
+        .collect::<Option<Vec<_>>>()
+        .map(|header_ips| header_ips.concat());
+
+    let header_ip = header_ips.and_then(|header_ips| {
+        header_ips
+            .iter()
+            .rev()
+            .find(|ip| !config.is_trusted_proxy(**ip))
+            .or_else(|| header_ips.first())
+            .copied()
+    });
+    match header_ip {
+        Some(header_ip) => Some((header_ip, IpSource::Header)),
+        None => Some((connecting_ip, IpSource::Client)),
+    }
+}
@@ -0,0 +1,361 @@
+use crate::dns::{DnsProtocol, Resolver, RESOLVERS};
+use crawler_verification::api_keys::ApiKeyMode;
+use crawler_verification::crawlers::{PtrSuffixes, CRAWLERS};
+use crawler_verification::ip_ranges::IpRanges;
+use fastly::http::StatusCode;
+use fastly::ConfigStore;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+/// The name of the Config Store holding this service's policy.
+/// Every key is optional: anything the store does not set, or if there is no such store,
+/// falls back to its default.
+const CONFIG_STORE: &str = "crawler_verification";
+
+/// The default `mode`: `api` serves the `/verify` lookup API,
+/// `inline` fronts the origin and verifies crawlers on the fly.
+const DEFAULT_MODE: &str = "api";
+
+/// The default `verified_header`: the response header carrying the `result` of a lookup,
+/// also added to requests forwarded to the origin in inline mode.
+const DEFAULT_VERIFIED_HEADER: &str = "x-googlebot-verified";
+
+/// The default `inline_policy`: what to do in inline mode with a request whose User-Agent
+/// claims to be a crawler it could not be verified as, `annotate`, `block` or `tarpit`.
+const DEFAULT_INLINE_POLICY: &str = "annotate";
+
+/// The default `resolver_strategy`: how queries are spread over the resolvers,
+/// `failover` or `race`.
+const DEFAULT_RESOLVER_STRATEGY: &str = "failover";
+
+/// The default `log_endpoint`: the name of the log endpoint verifications are logged to,
+/// when `logging` is `on`.
+const DEFAULT_LOG_ENDPOINT: &str = "verification_log";
+
+/// The default `token_header`: the header carrying the verification token of a lookup,
+/// also added to requests forwarded to the origin in inline mode.
+const DEFAULT_TOKEN_HEADER: &str = "x-verification-token";
+
+/// The default `token_ttl`: how long, in seconds, a verification token is valid for.
+const DEFAULT_TOKEN_TTL: u32 = 300;
+
+/// The default `dns_retries`: how many times a query is sent again to the same resolver
+/// after a transient failure, before failing over to the next one.
+const DEFAULT_DNS_RETRIES: u32 = 1;
+
+/// The default `dns_retry_backoff_ms`: the most to wait before the first retry of a query,
+/// doubled for every retry after it.
+const DEFAULT_DNS_RETRY_BACKOFF: Duration = Duration::from_millis(50);
+
+/// The default `api_key_header`: the header carrying a caller's API key,
+/// which may also be sent as a bearer token.
+const DEFAULT_API_KEY_HEADER: &str = "x-api-key";
+
+/// The default `rate_limit_penalty_s`: how long, in seconds, a caller over its rate limit
+/// is refused. Rounded down to whole minutes, from 1 to 60.
+const DEFAULT_RATE_LIMIT_PENALTY_S: u64 = 60;
+
+/// The results whose HTTP status can be set with `status_<result>`, e.g. `status_spoofed`.
+const CONFIGURABLE_STATUS_RESULTS: [&str; 3] = ["yes", "no", "spoofed"];
+
+/// A list of IP ranges whose verification result is forced, regardless of DNS.
+#[derive(Clone, Copy)]
+pub enum OverrideList {
+    /// IPs always verified, e.g. of an in-house monitoring crawler.
+    Allow,
+    /// IPs never verified, e.g. of an abused range.
+    Deny,
+}
+
+/// The timeouts of the backends created on the fly to reach the DNS resolvers,
+/// also used to fetch the published IP range lists.
+/// Unset timeouts keep their platform default.
+pub struct DnsTimeouts {
+    /// `dns_connect_timeout_ms`: how long to wait for a connection to the resolver.
+    pub connect: Option<Duration>,
+    /// `dns_first_byte_timeout_ms`: how long to wait for the first byte of the response.
+    pub first_byte: Option<Duration>,
+    /// `dns_between_bytes_timeout_ms`: how long to wait between bytes of the response.
+    pub between_bytes: Option<Duration>,
+}
+
+/// This service's policy, loaded from the [`CONFIG_STORE`] Config Store.
+pub struct Config {
+    /// `mode`: how this service handles client requests.
+    pub mode: String,
+    /// `verified_header`: the header carrying the `result` of a lookup.
+    pub verified_header: String,
+    /// `inline_policy`: what to do with unverified crawler requests in inline mode.
+    pub inline_policy: String,
+    /// `resolver_strategy`: how queries are spread over the resolvers.
+    pub resolver_strategy: String,
+    /// `resolvers`: the comma-separated names of the resolvers to query, in order.
+    /// Every resolver, in the order of [`RESOLVERS`], by default.
+    resolvers: Vec<&'static Resolver>,
+    /// `resolver_backend_<resolver>`, e.g. `resolver_backend_google`:
+    /// the backend to send a resolver's queries to, instead of its default one.
+    resolver_backends: HashMap<&'static str, String>,
+    /// `resolver_url_<resolver>`, e.g. `resolver_url_google`: the URL to send a resolver's
+    /// queries to, instead of its default one, e.g. that of an internal resolver.
+    /// The `Host` of the queries is the host of the URL.
+    resolver_urls: HashMap<&'static str, String>,
+    /// `resolver_protocol_<resolver>`, e.g. `resolver_protocol_google`: how to send a
+    /// resolver's queries, `json`, `rfc8484-get` or `rfc8484-post`, instead of its default way.
+    resolver_protocols: HashMap<&'static str, DnsProtocol>,
+    /// `ptr_suffixes_<crawler>`, e.g. `ptr_suffixes_googlebot`: the comma-separated domain
+    /// suffixes of a crawler's PTR records, replacing its built-in ones.
+    ptr_suffixes: PtrSuffixes,
+    /// `status_<result>`: the HTTP status of a lookup response with that result,
+    /// instead of `200 OK`.
+    statuses: HashMap<&'static str, StatusCode>,
+    /// `allow_list` and `deny_list`: comma-separated IPs and CIDR ranges.
+    override_lists: IpRanges<OverrideList>,
+    /// `client_ip_header`: the header holding the client IP when this service is behind
+    /// a proxy, e.g. `fastly-client-ip` or `x-forwarded-for`.
+    pub client_ip_header: Option<String>,
+    /// `trusted_proxies`: the comma-separated IPs and CIDR ranges of the proxies trusted
+    /// to set the `client_ip_header`. None by default, so the header is ignored.
+    trusted_proxies: IpRanges<()>,
+    /// `logging`: `on` to log every verification, `off` by default.
+    pub logging: bool,
+    /// `log_endpoint`: the log endpoint to write to.
+    pub log_endpoint: String,
+    /// `log_sample_rate`: the share of verifications to log, from 0 to 1, 1 by default.
+    pub log_sample_rate: f64,
+    /// `metrics`: `on` to log the counts and latencies of every request as `metrics` events,
+    /// and to serve the counts of the last minute on `/metrics`, `off` by default.
+    pub metrics: bool,
+    /// `tokens`: `on` to issue signed verification tokens, `off` by default.
+    pub tokens: bool,
+    /// `token_header`: the header carrying the verification token.
+    pub token_header: String,
+    /// `token_ttl`: how long, in seconds, a verification token is valid for.
+    pub token_ttl: u32,
+    /// The DNS timeouts, if any is set.
+    pub dns_timeouts: Option<DnsTimeouts>,
+    /// `latency_budget_ms`: how long a client request may wait on DNS resolvers in total.
+    /// Unlimited by default.
+    pub latency_budget: Option<Duration>,
+    /// `dns_retries`: how many times a query is retried after a transient failure.
+    pub dns_retries: u32,
+    /// `dns_retry_backoff_ms`: the most to wait before the first retry of a query.
+    pub dns_retry_backoff: Duration,
+    /// `api_keys`: whether lookup requests are authenticated by API key: `off` by default,
+    /// `optional` to authenticate the requests carrying a key, or `required`. Any other value
+    /// requires a key.
+    pub api_keys: ApiKeyMode,
+    /// `api_key_header`: the header carrying a caller's API key.
+    pub api_key_header: String,
+    /// `rate_limit_ip`: the most lookups per second a client IP may ask for, averaged over
+    /// 10 seconds, a batch counting as one per distinct IP. Unlimited by default.
+    pub rate_limit_ip: Option<u32>,
+    /// `rate_limit_api_key`: the most lookups per second an authenticated API key may ask
+    /// for, averaged over 10 seconds, unless the key sets its own. Unlimited by default.
+    pub rate_limit_api_key: Option<u32>,
+    /// `rate_limit_penalty_s`: how long a caller over its rate limit is refused.
+    pub rate_limit_penalty: Duration,
+    /// `dns_rate_limit`: the most DNS requests per second this service may send, across
+    /// every caller, averaged over 10 seconds. Counted per POP. Unlimited by default.
+    pub dns_rate_limit: Option<u32>,
+}
+
+/// The policy of this service, loaded on first use.
+pub fn config() -> &'static Config {
+    static CONFIG: OnceLock<Config> = OnceLock::new();
+    CONFIG.get_or_init(Config::load)
+}
+
+impl Config {
+    fn load() -> Config {
+        let store = ConfigStore::try_open(CONFIG_STORE).ok();
+        let get = |key: &str| -> Option<String> {
+            let value = store.as_ref()?.try_get(key).ok()??;
+            let value = value.trim();
+            (!value.is_empty()).then(|| value.to_string())
+        };
+
+        let mut resolvers: Vec<&'static Resolver> = get("resolvers")
+            .map(|names| {
+                split_list(&names)
+                    .filter_map(|name| RESOLVERS.iter().find(|resolver| resolver.name == name))
+                    .collect()
+            })
+            .unwrap_or_default();
+        if resolvers.is_empty() {
+            resolvers = RESOLVERS.iter().collect();
+        }
+        let per_resolver = |prefix: &str| -> HashMap<&'static str, String> {
+            RESOLVERS
+                .iter()
+                .filter_map(|resolver| {
+                    let value = get(&format!("{}{}", prefix, resolver.name))?;
+                    Some((resolver.name, value))
+                })
+                .collect()
+        };
+        let resolver_backends = per_resolver("resolver_backend_");
+        let resolver_urls = per_resolver("resolver_url_");
+        let resolver_protocols = per_resolver("resolver_protocol_")
+            .into_iter()
+            .filter_map(|(name, protocol)| Some((name, DnsProtocol::parse(&protocol)?)))
+            .collect();
+        // An empty list of PTR suffixes is meaningful: the crawler is only verified by IP ranges.
+        let ptr_suffixes = CRAWLERS
+            .iter()
+            .filter_map(|crawler| {
+                let key = format!("ptr_suffixes_{}", crawler.name);
+                let suffixes = store.as_ref()?.try_get(&key).ok()??;
+                Some((
+                    crawler.name,
+                    split_list(&suffixes).map(ptr_suffix).collect(),
+                ))
+            })
+            .collect();
+        let statuses = CONFIGURABLE_STATUS_RESULTS
+            .iter()
+            .filter_map(|result| {
+                let status = get(&format!("status_{}", result))?.parse::<u16>().ok()?;
+                Some((*result, StatusCode::from_u16(status).ok()?))
+            })
+            .collect();
+        let mut override_lists = IpRanges::default();
+        // The deny list is added last, so that it wins if both lists hold the same range.
+        for (key, list) in [
+            ("allow_list", OverrideList::Allow),
+            ("deny_list", OverrideList::Deny),
+        ] {
+            for prefix in get(key).iter().flat_map(|prefixes| split_list(prefixes)) {
+                override_lists.insert(prefix, list);
+            }
+        }
+
+        let millis = |key: &str| -> Option<Duration> {
+            get(key)?.parse::<u64>().ok().map(Duration::from_millis)
+        };
+        let dns_timeouts = DnsTimeouts {
+            connect: millis("dns_connect_timeout_ms"),
+            first_byte: millis("dns_first_byte_timeout_ms"),
+            between_bytes: millis("dns_between_bytes_timeout_ms"),
+        };
+        let any_dns_timeout = dns_timeouts.connect.is_some()
+            || dns_timeouts.first_byte.is_some()
+            || dns_timeouts.between_bytes.is_some();
+
+        let mut trusted_proxies = IpRanges::default();
+        for prefix in get("trusted_proxies")
+            .iter()
+            .flat_map(|prefixes| split_list(prefixes))
+        {
+            trusted_proxies.insert(prefix, ());
+        }
+
+        Config {
+            mode: get("mode").unwrap_or_else(|| DEFAULT_MODE.to_string()),
+            verified_header: get("verified_header")
+                .unwrap_or_else(|| DEFAULT_VERIFIED_HEADER.to_string()),
+            inline_policy: get("inline_policy")
+                .unwrap_or_else(|| DEFAULT_INLINE_POLICY.to_string()),
+            resolver_strategy: get("resolver_strategy")
+                .unwrap_or_else(|| DEFAULT_RESOLVER_STRATEGY.to_string()),
+            resolvers,
+            resolver_backends,
+            resolver_urls,
+            resolver_protocols,
+            ptr_suffixes,
+            statuses,
+            override_lists,
+            client_ip_header: get("client_ip_header"),
+            trusted_proxies,
+            logging: get("logging").as_deref() == Some("on"),
+            log_endpoint: get("log_endpoint").unwrap_or_else(|| DEFAULT_LOG_ENDPOINT.to_string()),
+            log_sample_rate: get("log_sample_rate")
+                .and_then(|rate| rate.parse::<f64>().ok())
+                .map_or(1.0, |rate| rate.clamp(0.0, 1.0)),
+            metrics: get("metrics").as_deref() == Some("on"),
+            tokens: get("tokens").as_deref() == Some("on"),
+            token_header: get("token_header").unwrap_or_else(|| DEFAULT_TOKEN_HEADER.to_string()),
+            token_ttl: get("token_ttl")
+                .and_then(|ttl| ttl.parse().ok())
+                .unwrap_or(DEFAULT_TOKEN_TTL),
+            dns_timeouts: any_dns_timeout.then_some(dns_timeouts),
+            latency_budget: millis("latency_budget_ms"),
+            dns_retries: get("dns_retries")
+                .and_then(|retries| retries.parse().ok())
+                .unwrap_or(DEFAULT_DNS_RETRIES),
+            dns_retry_backoff: millis("dns_retry_backoff_ms").unwrap_or(DEFAULT_DNS_RETRY_BACKOFF),
+            api_keys: ApiKeyMode::from_config(get("api_keys").as_deref()),
+            api_key_header: get("api_key_header")
+                .unwrap_or_else(|| DEFAULT_API_KEY_HEADER.to_string()),
+            rate_limit_ip: get("rate_limit_ip").and_then(|limit| limit.parse().ok()),
+            rate_limit_api_key: get("rate_limit_api_key").and_then(|limit| limit.parse().ok()),
+            // The penalty box only keeps entries for whole minutes, from 1 to 60.
+            rate_limit_penalty: Duration::from_secs(
+                get("rate_limit_penalty_s")
+                    .and_then(|penalty| penalty.parse::<u64>().ok())
+                    .map_or(DEFAULT_RATE_LIMIT_PENALTY_S, |penalty| {
+                        penalty.clamp(60, 3600) / 60 * 60
+                    }),
+            ),
+            dns_rate_limit: get("dns_rate_limit").and_then(|limit| limit.parse().ok()),
+        }
+    }
+
+    /// The resolvers to query, in order.
+    pub fn resolvers(&self) -> &[&'static Resolver] {
+        &self.resolvers
+    }
+
+    /// The backend to send the queries of `resolver` to, if it is not its default one.
+    pub fn resolver_backend(&self, resolver: &Resolver) -> Option<&str> {
+        self.resolver_backends
+            .get(resolver.name)
+            .map(String::as_str)
+    }
+
+    /// The URL to send the queries of `resolver` to, if it is not its default one.
+    pub fn resolver_url(&self, resolver: &Resolver) -> Option<&str> {
+        self.resolver_urls.get(resolver.name).map(String::as_str)
+    }
+
+    /// How to send the queries of `resolver`, if it is not its default way.
+    pub fn resolver_protocol(&self, resolver: &Resolver) -> Option<DnsProtocol> {
+        self.resolver_protocols.get(resolver.name).copied()
+    }
+
+    /// The configured PTR suffixes of the crawlers whose built-in ones they replace.
+    pub fn ptr_suffixes(&self) -> &PtrSuffixes {
+        &self.ptr_suffixes
+    }
+
+    /// The override list holding the most specific range containing `ip`, if any,
+    /// along with that range in CIDR notation.
+    pub fn override_list(&self, ip: IpAddr) -> Option<(OverrideList, String)> {
+        self.override_lists.find(ip)
+    }
+
+    /// Whether `ip` is a proxy trusted to set the `client_ip_header`.
+    pub fn is_trusted_proxy(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.find(ip).is_some()
+    }
+
+    /// The HTTP status of a lookup response with `result`, if it is not the default one.
+    pub fn status(&self, result: &str) -> Option<StatusCode> {
+        self.statuses.get(result).copied()
+    }
+}
+
+/// Split a comma-separated list, skipping empty items.
+fn split_list(list: &str) -> impl Iterator<Item = &str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+}
+
+/// Normalize a PTR suffix to lowercase, with a leading and a trailing dot,
+/// e.g. `googlebot.com` to `.googlebot.com.`.
+fn ptr_suffix(suffix: &str) -> String {
+    let suffix = suffix.trim_matches('.').to_ascii_lowercase();
+    format!(".{}.", suffix)
+}
@@ -0,0 +1,218 @@
+use std::collections::HashMap;
+
+/// The crawler verified when the client request does not name one with `?crawler=`.
+pub const DEFAULT_CRAWLER: &str = "googlebot";
+
+/// The `?crawler=` value that identifies the crawler from the IP instead of verifying a given one.
+pub const AUTO_CRAWLER: &str = "auto";
+
+/// The name of a backend server associated with this service.
+/// When configuring the backend using Fastly's UI, make sure it points to "developers.google.com".
+const GOOGLE_IP_RANGES: &str = "origin_1";
+
+/// The name of a backend server associated with this service.
+/// When configuring the backend using Fastly's UI, make sure it points to "www.bing.com".
+const BING_IP_RANGES: &str = "origin_2";
+
+/// The name of a backend server associated with this service.
+/// When configuring the backend using Fastly's UI, make sure it points to "search.developer.apple.com".
+const APPLE_IP_RANGES: &str = "origin_3";
+
+/// The name of a backend server associated with this service.
+/// When configuring the backend using Fastly's UI, make sure it points to "duckduckgo.com".
+const DUCKDUCKGO_IP_RANGES: &str = "origin_4";
+
+/// PTR suffixes replacing the built-in ones of some crawlers, by crawler name,
+/// in the same lowercase, dot-enclosed form as [`Crawler::ptr_suffixes`].
+pub type PtrSuffixes = HashMap<&'static str, Vec<String>>;
+
+/// A list of IP ranges published by a crawler operator, in the
+/// `{"prefixes": [{"ipv4Prefix": ...}, {"ipv6Prefix": ...}]}` format.
+pub struct IpRangeList {
+    /// The name of the list, e.g. `special-crawlers`.
+    pub name: &'static str,
+    /// Where the list is published.
+    pub url: &'static str,
+    /// The backend serving `url`.
+    pub backend: &'static str,
+}
+
+/// A crawler that can be verified.
+pub struct Crawler {
+    /// The name of the crawler, as accepted by `?crawler=`.
+    pub name: &'static str,
+    /// The lowercase product tokens identifying the crawler in a User-Agent.
+    pub user_agent_tokens: &'static [&'static str],
+    /// The domain suffixes of the crawler's PTR records, including the trailing dot,
+    /// unless the Config Store replaces them.
+    pub ptr_suffixes: &'static [&'static str],
+    /// The IP range lists published for the crawler, if any.
+    pub ip_range_lists: &'static [IpRangeList],
+}
+
+/// Every crawler this service knows how to verify.
+pub static CRAWLERS: [Crawler; 6] = [
+    Crawler {
+        name: "googlebot",
+        user_agent_tokens: &[
+            "googlebot",
+            "adsbot-google",
+            "mediapartners-google",
+            "apis-google",
+            "feedfetcher-google",
+            "googleother",
+            "google-inspectiontool",
+            "storebot-google",
+        ],
+        ptr_suffixes: &[".googlebot.com.", ".google.com."],
+        ip_range_lists: &[
+            IpRangeList {
+                name: "googlebot",
+                url: "https://developers.google.com/static/search/apis/ipranges/googlebot.json",
+                backend: GOOGLE_IP_RANGES,
+            },
+            IpRangeList {
+                name: "special-crawlers",
+                url: "https://developers.google.com/static/search/apis/ipranges/special-crawlers.json",
+                backend: GOOGLE_IP_RANGES,
+            },
+            IpRangeList {
+                name: "user-triggered-fetchers",
+                url: "https://developers.google.com/static/search/apis/ipranges/user-triggered-fetchers.json",
+                backend: GOOGLE_IP_RANGES,
+            },
+        ],
+    },
+    Crawler {
+        name: "bingbot",
+        user_agent_tokens: &["bingbot", "bingpreview", "adidxbot", "msnbot"],
+        ptr_suffixes: &[".search.msn.com."],
+        ip_range_lists: &[IpRangeList {
+            name: "bingbot",
+            url: "https://www.bing.com/toolbox/bingbot.json",
+            backend: BING_IP_RANGES,
+        }],
+    },
+    Crawler {
+        name: "applebot",
+        user_agent_tokens: &["applebot"],
+        ptr_suffixes: &[".applebot.apple.com."],
+        ip_range_lists: &[IpRangeList {
+            name: "applebot",
+            url: "https://search.developer.apple.com/applebot.json",
+            backend: APPLE_IP_RANGES,
+        }],
+    },
+    Crawler {
+        name: "duckduckbot",
+        user_agent_tokens: &["duckduckbot"],
+        ptr_suffixes: &[],
+        ip_range_lists: &[IpRangeList {
+            name: "duckduckbot",
+            url: "https://duckduckgo.com/duckduckbot.json",
+            backend: DUCKDUCKGO_IP_RANGES,
+        }],
+    },
+    Crawler {
+        name: "yandexbot",
+        user_agent_tokens: &["yandex"],
+        ptr_suffixes: &[".yandex.ru.", ".yandex.net.", ".yandex.com."],
+        ip_range_lists: &[],
+    },
+    Crawler {
+        name: "baiduspider",
+        user_agent_tokens: &["baiduspider"],
+        ptr_suffixes: &[".baidu.com.", ".baidu.jp."],
+        ip_range_lists: &[],
+    },
+];
+
+impl Crawler {
+    /// Find a crawler by name, ignoring case.
+    pub fn find(name: &str) -> Option<&'static Crawler> {
+        CRAWLERS
+            .iter()
+            .find(|crawler| crawler.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Find the crawler a User-Agent claims to be, if any.
+    pub fn find_by_user_agent(user_agent: &str) -> Option<&'static Crawler> {
+        let user_agent = user_agent.to_ascii_lowercase();
+        CRAWLERS.iter().find(|crawler| {
+            crawler
+                .user_agent_tokens
+                .iter()
+                .any(|token| user_agent.contains(token))
+        })
+    }
+
+    /// Whether `domain`, a PTR record, belongs to this crawler,
+    /// going by its PTR suffixes in `ptr_suffixes` if there are any.
+    pub fn matches_ptr(&self, domain: &str, ptr_suffixes: &PtrSuffixes) -> bool {
+        let domain = domain.to_ascii_lowercase();
+        match ptr_suffixes.get(self.name) {
+            Some(suffixes) => suffixes.iter().any(|suffix| domain.ends_with(suffix)),
+            None => self
+                .ptr_suffixes
+                .iter()
+                .any(|suffix| domain.ends_with(suffix)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn find_ignores_case() {
+        assert_eq!(Crawler::find("GoogleBot").unwrap().name, "googlebot");
+        assert!(Crawler::find("examplebot").is_none());
+    }
+
+    #[test]
+    fn find_by_user_agent() {
+        let find = |user_agent| Crawler::find_by_user_agent(user_agent).map(|crawler| crawler.name);
+        assert_eq!(
+            find("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"),
+            Some("googlebot")
+        );
+        assert_eq!(
+            find("Mozilla/5.0 (compatible; bingbot/2.0; +http://www.bing.com/bingbot.htm)"),
+            Some("bingbot")
+        );
+        assert_eq!(
+            find("Mozilla/5.0 (X11; Linux x86_64; rv:120.0) Firefox/120.0"),
+            None
+        );
+    }
+
+    #[test]
+    fn matches_ptr_by_built_in_suffix() {
+        let googlebot = Crawler::find("googlebot").unwrap();
+        let no_overrides = PtrSuffixes::new();
+        assert!(googlebot.matches_ptr("crawl-66-249-66-1.googlebot.com.", &no_overrides));
+        assert!(googlebot.matches_ptr("rate-limited-proxy-66-249-90-1.google.com.", &no_overrides));
+        assert!(googlebot.matches_ptr("CRAWL-66-249-66-1.GOOGLEBOT.COM.", &no_overrides));
+        // The suffix must be a whole domain, not the end of a label.
+        assert!(!googlebot.matches_ptr("crawl.notgooglebot.com.", &no_overrides));
+        assert!(!googlebot.matches_ptr("googlebot.com.example.com.", &no_overrides));
+    }
+
+    #[test]
+    fn matches_ptr_without_suffixes() {
+        let duckduckbot = Crawler::find("duckduckbot").unwrap();
+        assert!(!duckduckbot.matches_ptr("duckduckbot.duckduckgo.com.", &PtrSuffixes::new()));
+    }
+
+    #[test]
+    fn configured_suffixes_replace_the_built_in_ones() {
+        let googlebot = Crawler::find("googlebot").unwrap();
+        let ptr_suffixes = PtrSuffixes::from([("googlebot", vec![".example.com.".to_string()])]);
+        assert!(googlebot.matches_ptr("crawl.example.com.", &ptr_suffixes));
+        assert!(!googlebot.matches_ptr("crawl-66-249-66-1.googlebot.com.", &ptr_suffixes));
+
+        let bingbot = Crawler::find("bingbot").unwrap();
+        assert!(bingbot.matches_ptr("msnbot-157-55-39-1.search.msn.com.", &ptr_suffixes));
+    }
+}
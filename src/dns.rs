@@ -0,0 +1,448 @@
+use crate::config::{config, DnsTimeouts};
+use crate::latency_budget;
+use crate::{metrics, rate_limit};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use crawler_verification::dns_message;
+pub use crawler_verification::dns_response::{
+    reverse_lookup_name, DnsError, DnsResponse, RecordType,
+};
+use crawler_verification::verify::Resolve;
+use fastly::backend::{Backend, BackendCreationError};
+use fastly::http::request::{PendingRequest, SendError, SendErrorCause};
+use fastly::http::{header, StatusCode};
+use fastly::{Request, Response};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// The name of a backend server associated with this service.
+/// When configuring the backend using Fastly's UI, make sure it points to "dns.google.com".
+const GOOGLE_DNS: &str = "origin_0";
+
+/// The name of a backend server associated with this service.
+/// When configuring the backend using Fastly's UI, make sure it points to "cloudflare-dns.com".
+const CLOUDFLARE_DNS: &str = "origin_6";
+
+/// The name of a backend server associated with this service.
+/// When configuring the backend using Fastly's UI, make sure it points to "dns.quad9.net".
+const QUAD9_DNS: &str = "origin_7";
+
+/// How many DNS requests were sent for the client request, counting retries and failovers.
+static ATTEMPTS: AtomicU32 = AtomicU32::new(0);
+
+/// The media type of RFC 8484 DNS messages.
+const DNS_MESSAGE_CONTENT_TYPE: &str = "application/dns-message";
+
+/// How queries are sent to a [`Resolver`].
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DnsProtocol {
+    /// The Google DNS JSON API.
+    Json,
+    /// RFC 8484 DNS messages, base64url-encoded in the `?dns=` query string of a GET request.
+    Rfc8484Get,
+    /// RFC 8484 DNS messages in the body of a POST request.
+    Rfc8484Post,
+}
+
+impl DnsProtocol {
+    /// The protocol named by a `resolver_protocol_<resolver>` value, if it is a known one.
+    pub fn parse(protocol: &str) -> Option<DnsProtocol> {
+        match protocol {
+            "json" => Some(DnsProtocol::Json),
+            "rfc8484-get" => Some(DnsProtocol::Rfc8484Get),
+            "rfc8484-post" => Some(DnsProtocol::Rfc8484Post),
+            _ => None,
+        }
+    }
+}
+
+/// A DNS-over-HTTPS resolver. Its URL and protocol can be set in the Config Store, e.g. to
+/// send its queries to an internal resolver instead.
+pub struct Resolver {
+    /// The name of the resolver, as reported in responses.
+    pub name: &'static str,
+    /// The backend serving `url`, unless the Config Store sets another one.
+    backend: &'static str,
+    /// How queries are sent to the resolver, unless the Config Store sets another way.
+    protocol: DnsProtocol,
+    /// Where queries are sent, e.g. `https://dns.quad9.net/dns-query` for RFC 8484 resolvers,
+    /// unless the Config Store sets another URL.
+    url: &'static str,
+    /// The name of the backend created on the fly to serve `url` when DNS timeouts are set.
+    dynamic_backend: &'static str,
+    /// Whether the dynamic backend could be created, once it was tried.
+    dynamic_backend_created: OnceLock<bool>,
+}
+
+/// The resolvers queries can be sent to, in their default order of preference.
+pub static RESOLVERS: [Resolver; 3] = [
+    Resolver {
+        name: "google",
+        backend: GOOGLE_DNS,
+        protocol: DnsProtocol::Json,
+        url: "https://dns.google.com/resolve",
+        dynamic_backend: "dns_google",
+        dynamic_backend_created: OnceLock::new(),
+    },
+    Resolver {
+        name: "cloudflare",
+        backend: CLOUDFLARE_DNS,
+        protocol: DnsProtocol::Rfc8484Get,
+        url: "https://cloudflare-dns.com/dns-query",
+        dynamic_backend: "dns_cloudflare",
+        dynamic_backend_created: OnceLock::new(),
+    },
+    Resolver {
+        name: "quad9",
+        backend: QUAD9_DNS,
+        protocol: DnsProtocol::Rfc8484Post,
+        url: "https://dns.quad9.net/dns-query",
+        dynamic_backend: "dns_quad9",
+        dynamic_backend_created: OnceLock::new(),
+    },
+];
+
+/// How queries are spread over the configured resolvers.
+#[derive(PartialEq, Eq)]
+enum ResolverStrategy {
+    /// Send each query to the first resolver, and to the next one only if it fails.
+    Failover,
+    /// Send each query to the first two resolvers at once, and take the first valid answer.
+    Race,
+}
+
+impl ResolverStrategy {
+    /// The configured strategy, [`ResolverStrategy::Failover`] if it is not a known one.
+    fn configured() -> ResolverStrategy {
+        match config().resolver_strategy.as_str() {
+            "race" => ResolverStrategy::Race,
+            _ => ResolverStrategy::Failover,
+        }
+    }
+}
+
+/// The [`DnsError`] of a DNS request that could not be sent or got no response, telling
+/// a resolver that did not answer in time from one that could not be reached.
+pub fn send_error(error: &SendError) -> DnsError {
+    match error.root_cause() {
+        SendErrorCause::HttpResponseTimeout
+        | SendErrorCause::ConnectionTimeout
+        | SendErrorCause::DnsTimeout => DnsError::Timeout,
+        _ => DnsError::Unavailable,
+    }
+}
+
+/// Create a backend named `name` on the fly, for the HTTPS host of `url`, with the DNS timeouts.
+/// Returns whether the backend exists, either created now or earlier in the client request.
+pub fn create_timed_backend(name: &str, url: &str, dns_timeouts: &DnsTimeouts) -> bool {
+    let host = url
+        .trim_start_matches("https://")
+        .split('/')
+        .next()
+        .unwrap_or_default();
+    let mut builder = Backend::builder(name, format!("{}:443", host))
+        .override_host(host)
+        .enable_ssl()
+        .sni_hostname(host);
+    if let Some(timeout) = dns_timeouts.connect {
+        builder = builder.connect_timeout(timeout);
+    }
+    if let Some(timeout) = dns_timeouts.first_byte {
+        builder = builder.first_byte_timeout(timeout);
+    }
+    if let Some(timeout) = dns_timeouts.between_bytes {
+        builder = builder.between_bytes_timeout(timeout);
+    }
+    matches!(
+        builder.finish(),
+        Ok(_) | Err(BackendCreationError::NameInUse)
+    )
+}
+
+/// The configured resolvers, queried following the configured strategy.
+pub struct ConfiguredResolvers;
+
+impl Resolve for ConfiguredResolvers {
+    fn resolve(&self, name: &str, record_type: RecordType) -> Result<DnsResponse, DnsError> {
+        resolve(name, record_type)
+    }
+}
+
+/// How many DNS requests were sent for the client request so far, counting retries and failovers.
+pub fn attempts() -> u32 {
+    ATTEMPTS.load(Ordering::Relaxed)
+}
+
+/// How long to wait before retry number `retry` of a query: a random delay of up to the
+/// configured backoff, doubled for every retry before it.
+fn retry_backoff(retry: u32) -> Duration {
+    let max_backoff = config().dns_retry_backoff * 2u32.saturating_pow(retry.saturating_sub(1));
+    max_backoff.mul_f64(rand::random::<f64>())
+}
+
+/// Query the configured resolvers for the `record_type` records of `name`, following the
+/// configured strategy. Returns the error of the last resolver if every one of them fails,
+/// or [`DnsError::Timeout`] once the latency budget runs out.
+pub fn resolve(name: &str, record_type: RecordType) -> Result<DnsResponse, DnsError> {
+    let mut last_error = DnsError::Unavailable;
+    let mut resolvers = config().resolvers().iter().copied();
+
+    if ResolverStrategy::configured() == ResolverStrategy::Race {
+        match race(name, record_type, resolvers.by_ref().take(2)) {
+            Ok(dns_response) => return Ok(dns_response),
+            Err(error) => last_error = error,
+        }
+    }
+
+    for resolver in resolvers {
+        match resolver.resolve(name, record_type) {
+            Ok(dns_response) => return Ok(dns_response),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+/// Send the query to every one of `resolvers` at once, and take the first valid answer.
+fn race(
+    name: &str,
+    record_type: RecordType,
+    resolvers: impl Iterator<Item = &'static Resolver>,
+) -> Result<DnsResponse, DnsError> {
+    let started = Instant::now();
+    let mut last_error = DnsError::Unavailable;
+    let mut pending_requests = Vec::new();
+    // The resolvers that have yet to answer.
+    let mut waiting = Vec::new();
+    for resolver in resolvers {
+        let pending_request = resolver
+            .request(name, record_type)
+            .and_then(|dns_request| resolver.send_async(dns_request));
+        match pending_request {
+            Ok(pending_request) => {
+                pending_requests.push(pending_request);
+                waiting.push(resolver);
+            }
+            Err(error) => {
+                metrics::record_dns_request(resolver.name, &Err(error), started.elapsed());
+                last_error = error;
+            }
+        }
+    }
+
+    while !pending_requests.is_empty() {
+        let (beresp, remaining) = match latency_budget::select_within_budget(pending_requests) {
+            Some(selected) => selected,
+            None => {
+                for resolver in waiting {
+                    let timeout = Err(DnsError::Timeout);
+                    metrics::record_dns_request(resolver.name, &timeout, started.elapsed());
+                }
+                return Err(DnsError::Timeout);
+            }
+        };
+        pending_requests = remaining;
+        let (resolver, dns_response) = match beresp {
+            Ok(beresp) => {
+                let resolver = beresp
+                    .get_backend_name()
+                    .and_then(Resolver::find_by_backend);
+                let dns_response = match resolver {
+                    Some(resolver) => resolver.parse_response(beresp),
+                    None => Err(DnsError::Unavailable),
+                };
+                (resolver, dns_response)
+            }
+            Err(error) => (
+                Resolver::find_by_backend(error.backend_name()),
+                Err(send_error(&error)),
+            ),
+        };
+        if let Some(resolver) = resolver {
+            waiting.retain(|waiting_resolver| !std::ptr::eq(*waiting_resolver, resolver));
+            metrics::record_dns_request(resolver.name, &dns_response, started.elapsed());
+        }
+        match dns_response {
+            Ok(dns_response) => return Ok(dns_response),
+            Err(error) => last_error = error,
+        }
+    }
+    Err(last_error)
+}
+
+impl Resolver {
+    /// Find a configured resolver by the name of its backend.
+    fn find_by_backend(backend: &str) -> Option<&'static Resolver> {
+        config()
+            .resolvers()
+            .iter()
+            .copied()
+            .find(|resolver| resolver.backend() == backend)
+    }
+
+    /// The backend serving the resolver's URL: the one set in the Config Store, or the dynamic
+    /// backend if DNS timeouts are set, or else its default one.
+    fn backend(&self) -> &str {
+        config()
+            .resolver_backend(self)
+            .or_else(|| self.dynamic_backend())
+            .unwrap_or(self.backend)
+    }
+
+    /// Where queries are sent: the URL set in the Config Store, or else its default one.
+    fn url(&self) -> &str {
+        config().resolver_url(self).unwrap_or(self.url)
+    }
+
+    /// How queries are sent: the protocol set in the Config Store, or else its default one.
+    fn protocol(&self) -> DnsProtocol {
+        config().resolver_protocol(self).unwrap_or(self.protocol)
+    }
+
+    /// The backend created on the fly with the configured DNS timeouts, if any are set and it
+    /// could be created. Dynamic backends must be enabled for the service.
+    fn dynamic_backend(&self) -> Option<&str> {
+        let dns_timeouts = config().dns_timeouts.as_ref()?;
+        let created = *self
+            .dynamic_backend_created
+            .get_or_init(|| create_timed_backend(self.dynamic_backend, self.url(), dns_timeouts));
+        created.then_some(self.dynamic_backend)
+    }
+
+    /// Query this resolver for the `record_type` records of `name`, retrying transient
+    /// failures up to the configured number of times, as long as the latency budget allows.
+    fn resolve(
+        &'static self,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<DnsResponse, DnsError> {
+        let mut retry = 0;
+        loop {
+            match self.resolve_once(name, record_type) {
+                Err(error) if error.is_retryable() && retry < config().dns_retries => {
+                    retry += 1;
+                    if !latency_budget::sleep_within_budget(retry_backoff(retry)) {
+                        return Err(error);
+                    }
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Query this resolver for the `record_type` records of `name`, once.
+    fn resolve_once(
+        &'static self,
+        name: &str,
+        record_type: RecordType,
+    ) -> Result<DnsResponse, DnsError> {
+        let dns_request = self.request(name, record_type)?;
+        let started = Instant::now();
+        let dns_response = self.send_async(dns_request).and_then(|pending_request| {
+            let (beresp, _) = latency_budget::select_within_budget(vec![pending_request])
+                .ok_or(DnsError::Timeout)?;
+            self.parse_response(beresp.map_err(|error| send_error(&error))?)
+        });
+        metrics::record_dns_request(self.name, &dns_response, started.elapsed());
+        dns_response
+    }
+
+    /// Send `dns_request`, built by this resolver, without waiting for the response.
+    /// Nothing is sent once the latency budget has run out, or the DNS request budget is spent.
+    pub fn send_async(&self, dns_request: Request) -> Result<PendingRequest, DnsError> {
+        if latency_budget::is_exhausted() {
+            return Err(DnsError::Timeout);
+        }
+        if rate_limit::dns_budget_spent() {
+            return Err(DnsError::RateLimited);
+        }
+        ATTEMPTS.fetch_add(1, Ordering::Relaxed);
+        rate_limit::count_dns_request();
+        dns_request
+            .send_async(self.backend())
+            .map_err(|error| send_error(&error))
+    }
+
+    /// Build a request for the `record_type` records of `name`.
+    fn request(&self, name: &str, record_type: RecordType) -> Result<Request, DnsError> {
+        self.build_request(name, record_type, self.protocol())
+    }
+
+    /// Like [`Resolver::request`], but always a GET request, so that every query has its own
+    /// URL to tell its response apart by. RFC 8484 resolvers support both GET and POST.
+    pub fn get_request(&self, name: &str, record_type: RecordType) -> Result<Request, DnsError> {
+        let protocol = match self.protocol() {
+            DnsProtocol::Rfc8484Post => DnsProtocol::Rfc8484Get,
+            protocol => protocol,
+        };
+        self.build_request(name, record_type, protocol)
+    }
+
+    fn build_request(
+        &self,
+        name: &str,
+        record_type: RecordType,
+        protocol: DnsProtocol,
+    ) -> Result<Request, DnsError> {
+        if protocol == DnsProtocol::Json {
+            let uri = format!(
+                "{}?name={}&type={}",
+                self.url(),
+                percent_encode(name),
+                record_type.name()
+            );
+            return Ok(Request::get(uri));
+        }
+
+        let query = dns_message::encode_query(name, record_type).ok_or(DnsError::InvalidName)?;
+        let dns_request = match protocol {
+            DnsProtocol::Rfc8484Post => Request::post(self.url())
+                .with_header(header::CONTENT_TYPE, DNS_MESSAGE_CONTENT_TYPE)
+                .with_body(query),
+            _ => Request::get(format!(
+                "{}?dns={}",
+                self.url(),
+                URL_SAFE_NO_PAD.encode(query)
+            )),
+        };
+        Ok(dns_request.with_header(header::ACCEPT, DNS_MESSAGE_CONTENT_TYPE))
+    }
+
+    /// Parse a response of this resolver.
+    /// NXDOMAIN is a valid response, any other error status is not.
+    pub fn parse_response(&'static self, mut beresp: Response) -> Result<DnsResponse, DnsError> {
+        let status = beresp.get_status();
+        if status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS {
+            return Err(DnsError::Unavailable);
+        }
+        if !status.is_success() {
+            return Err(DnsError::Rejected);
+        }
+
+        let mut dns_response = match self.protocol() {
+            DnsProtocol::Json => DnsResponse::from_json(&beresp.take_body_str()),
+            DnsProtocol::Rfc8484Get | DnsProtocol::Rfc8484Post => {
+                dns_message::decode_response(&beresp.take_body_bytes())
+            }
+        }
+        .ok_or(DnsError::InvalidResponse)?
+        .check()?;
+        dns_response.resolver = Some(self.name);
+        Ok(dns_response)
+    }
+}
+
+/// Percent-encode `value` for a query string, leaving only the unreserved characters of
+/// RFC 3986 as they are, so that a name can never add to or cut short the query string.
+fn percent_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
@@ -0,0 +1,372 @@
+use crate::dns_response::{DnsAnswer, DnsResponse, RecordType};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// The length of a DNS message header.
+const HEADER_LEN: usize = 12;
+
+/// The DNS class of Internet records.
+const CLASS_IN: u16 = 1;
+
+/// The header flag set on responses (QR).
+const FLAG_RESPONSE: u16 = 0x8000;
+
+/// The header flag set on truncated messages (TC).
+const FLAG_TRUNCATED: u16 = 0x0200;
+
+/// The header flag asking the resolver to resolve the query recursively (RD).
+const FLAG_RECURSION_DESIRED: u16 = 0x0100;
+
+/// The bits of the header flags holding the response code.
+const RCODE_MASK: u16 = 0x000f;
+
+/// The DNS resource record type code for a canonical name (CNAME) record.
+const TYPE_CNAME: u16 = 5;
+
+/// The longest a label may be.
+const MAX_LABEL_LEN: usize = 63;
+
+/// The longest a name may be, in its wire format.
+const MAX_NAME_LEN: usize = 255;
+
+/// The most compression pointers followed while decoding a single name,
+/// which guards against pointer loops.
+const MAX_POINTERS: usize = 32;
+
+/// Encode an RFC 1035 query message for the `record_type` records of `name`.
+///
+/// The message ID is 0, as recommended by RFC 8484 to make DoH GET responses cacheable.
+/// Returns `None` if `name` is not a valid domain name.
+pub fn encode_query(name: &str, record_type: RecordType) -> Option<Vec<u8>> {
+    let mut message = Vec::with_capacity(HEADER_LEN + name.len() + 6);
+    message.extend_from_slice(&0u16.to_be_bytes());
+    message.extend_from_slice(&FLAG_RECURSION_DESIRED.to_be_bytes());
+    message.extend_from_slice(&1u16.to_be_bytes());
+    message.extend_from_slice(&[0; 6]);
+    encode_name(name, &mut message)?;
+    message.extend_from_slice(&record_type.code().to_be_bytes());
+    message.extend_from_slice(&CLASS_IN.to_be_bytes());
+    Some(message)
+}
+
+/// Decode an RFC 1035 response message.
+///
+/// A and AAAA answers are decoded into IP addresses, and PTR and CNAME answers into
+/// fully qualified names with a trailing dot, like the Google DNS JSON API does.
+/// Answers of other types are skipped. Returns `None` if the message is malformed.
+pub fn decode_response(message: &[u8]) -> Option<DnsResponse> {
+    let mut reader = Reader {
+        message,
+        position: 0,
+    };
+    reader.read_u16()?;
+    let flags = reader.read_u16()?;
+    let question_count = reader.read_u16()?;
+    let answer_count = reader.read_u16()?;
+    reader.skip(4)?;
+    if flags & FLAG_RESPONSE == 0 {
+        return None;
+    }
+
+    for _ in 0..question_count {
+        reader.read_name()?;
+        reader.skip(4)?;
+    }
+
+    let mut answer = Vec::with_capacity(answer_count.into());
+    for _ in 0..answer_count {
+        reader.read_name()?;
+        let record_type = reader.read_u16()?;
+        reader.read_u16()?;
+        let ttl = reader.read_u32()?;
+        let data_len = usize::from(reader.read_u16()?);
+        let data_start = reader.position;
+        let data = reader.take(data_len)?;
+
+        let data = match record_type {
+            code if code == RecordType::A.code() => {
+                Ipv4Addr::from(<[u8; 4]>::try_from(data).ok()?).to_string()
+            }
+            code if code == RecordType::Aaaa.code() => {
+                Ipv6Addr::from(<[u8; 16]>::try_from(data).ok()?).to_string()
+            }
+            code if code == RecordType::Ptr.code() || code == TYPE_CNAME => Reader {
+                message,
+                position: data_start,
+            }
+            .read_name()?,
+            _ => continue,
+        };
+        answer.push(DnsAnswer {
+            record_type,
+            ttl,
+            data,
+        });
+    }
+
+    Some(DnsResponse {
+        resolver: None,
+        status: flags & RCODE_MASK,
+        truncated: flags & FLAG_TRUNCATED != 0,
+        answer,
+    })
+}
+
+/// Append the wire format of `name`, in presentation format, to `message`.
+///
+/// `\.` and `\\` escape a dot or backslash within a label, and `\DDD` any byte, as written
+/// by [`Reader::read_name`].
+fn encode_name(name: &str, message: &mut Vec<u8>) -> Option<()> {
+    let name_start = message.len();
+    let mut label = Vec::new();
+    if name != "." {
+        let mut bytes = name.bytes();
+        while let Some(byte) = bytes.next() {
+            match byte {
+                b'.' => encode_label(&mut label, message)?,
+                b'\\' => label.push(match bytes.next()? {
+                    digit @ b'0'..=b'9' => {
+                        let digits = [digit, bytes.next()?, bytes.next()?];
+                        std::str::from_utf8(&digits).ok()?.parse().ok()?
+                    }
+                    escaped => escaped,
+                }),
+                byte => label.push(byte),
+            }
+        }
+        // The trailing dot of a fully qualified name is optional.
+        if !label.is_empty() {
+            encode_label(&mut label, message)?;
+        }
+    }
+    message.push(0);
+
+    if message.len() - name_start > MAX_NAME_LEN {
+        return None;
+    }
+    Some(())
+}
+
+/// Append `label`, which must not be empty, to `message`, leaving `label` empty.
+fn encode_label(label: &mut Vec<u8>, message: &mut Vec<u8>) -> Option<()> {
+    if label.is_empty() || label.len() > MAX_LABEL_LEN {
+        return None;
+    }
+    message.push(label.len() as u8);
+    message.append(label);
+    Some(())
+}
+
+/// A cursor over a DNS message.
+struct Reader<'a> {
+    message: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let bytes = self
+            .message
+            .get(self.position..self.position.checked_add(len)?)?;
+        self.position += len;
+        Some(bytes)
+    }
+
+    fn skip(&mut self, len: usize) -> Option<()> {
+        self.take(len).map(|_| ())
+    }
+
+    fn read_u16(&mut self) -> Option<u16> {
+        Some(u16::from_be_bytes(self.take(2)?.try_into().ok()?))
+    }
+
+    fn read_u32(&mut self) -> Option<u32> {
+        Some(u32::from_be_bytes(self.take(4)?.try_into().ok()?))
+    }
+
+    /// Read a possibly compressed name, in presentation format with a trailing dot.
+    ///
+    /// Letters, digits, `-` and `_` are kept as they are; a dot or backslash within a label is
+    /// escaped with a backslash, and any other byte as `\DDD`. This keeps a label holding
+    /// dots from passing for several labels, e.g. for a crawler's domain suffix.
+    fn read_name(&mut self) -> Option<String> {
+        let mut name = String::new();
+        let mut name_len = 0;
+        let mut pointers = 0;
+        // Where to continue once the name is read, if it jumped to a compression pointer.
+        let mut end = None;
+        let mut position = self.position;
+
+        loop {
+            let len = usize::from(*self.message.get(position)?);
+            match len & 0xc0 {
+                0x00 if len == 0 => {
+                    position += 1;
+                    break;
+                }
+                0x00 => {
+                    let label = self.message.get(position + 1..position + 1 + len)?;
+                    name_len += 1 + len;
+                    if name_len + 1 > MAX_NAME_LEN {
+                        return None;
+                    }
+                    for &byte in label {
+                        match byte {
+                            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' | b'-' | b'_' => {
+                                name.push(byte.into())
+                            }
+                            b'.' | b'\\' => {
+                                name.push('\\');
+                                name.push(byte.into());
+                            }
+                            _ => name.push_str(&format!("\\{:03}", byte)),
+                        }
+                    }
+                    name.push('.');
+                    position += 1 + len;
+                }
+                0xc0 => {
+                    pointers += 1;
+                    if pointers > MAX_POINTERS {
+                        return None;
+                    }
+                    let low = usize::from(*self.message.get(position + 1)?);
+                    end.get_or_insert(position + 2);
+                    position = ((len & 0x3f) << 8) | low;
+                }
+                _ => return None,
+            }
+        }
+
+        self.position = end.unwrap_or(position);
+        if name.is_empty() {
+            name.push('.');
+        }
+        Some(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A pointer to the name of the question, which follows the header.
+    const QUESTION_NAME: [u8; 2] = [0xc0, HEADER_LEN as u8];
+
+    /// Turn the query `message` into a response with `rcode`, answering it with `answers`:
+    /// records of the queried name as `(type, data)`.
+    fn respond(mut message: Vec<u8>, rcode: u16, answers: &[(u16, &[u8])]) -> Vec<u8> {
+        let flags = FLAG_RESPONSE | FLAG_RECURSION_DESIRED | rcode;
+        message[2..4].copy_from_slice(&flags.to_be_bytes());
+        message[6..8].copy_from_slice(&(answers.len() as u16).to_be_bytes());
+        for (record_type, data) in answers {
+            message.extend_from_slice(&QUESTION_NAME);
+            message.extend_from_slice(&record_type.to_be_bytes());
+            message.extend_from_slice(&CLASS_IN.to_be_bytes());
+            message.extend_from_slice(&300u32.to_be_bytes());
+            message.extend_from_slice(&(data.len() as u16).to_be_bytes());
+            message.extend_from_slice(data);
+        }
+        message
+    }
+
+    #[test]
+    fn encodes_a_query() {
+        assert_eq!(
+            encode_query("example.com.", RecordType::Aaaa).unwrap(),
+            [
+                0, 0, 1, 0, 0, 1, 0, 0, 0, 0, 0, 0, // header
+                7, b'e', b'x', b'a', b'm', b'p', b'l', b'e', 3, b'c', b'o', b'm', 0, // name
+                0, 28, 0, 1, // type and class
+            ]
+        );
+        assert_eq!(
+            encode_query("example.com", RecordType::Aaaa),
+            encode_query("example.com.", RecordType::Aaaa)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_names() {
+        assert!(encode_query("example..com", RecordType::A).is_none());
+        assert!(encode_query(&format!("{}.com", "a".repeat(64)), RecordType::A).is_none());
+        let long_name = vec!["a".repeat(63); 4].join(".");
+        assert!(encode_query(&long_name, RecordType::A).is_none());
+    }
+
+    #[test]
+    fn decodes_answers() {
+        let query = encode_query("crawl-66-249-66-1.googlebot.com.", RecordType::A).unwrap();
+        let ipv6 = "2001:4860:4801:10::1".parse::<Ipv6Addr>().unwrap().octets();
+        let txt = [4, b't', b'e', b'x', b't'];
+        let message = respond(
+            query,
+            0,
+            &[
+                (RecordType::A.code(), &[66, 249, 66, 1]),
+                (RecordType::Aaaa.code(), &ipv6),
+                (16, &txt),
+                (RecordType::Ptr.code(), &QUESTION_NAME),
+            ],
+        );
+
+        let dns_response = decode_response(&message).unwrap();
+        assert_eq!(dns_response.status, 0);
+        assert!(!dns_response.truncated);
+        assert_eq!(
+            dns_response.answers(RecordType::A).collect::<Vec<_>>(),
+            ["66.249.66.1"]
+        );
+        assert_eq!(
+            dns_response.answers(RecordType::Aaaa).collect::<Vec<_>>(),
+            ["2001:4860:4801:10::1"]
+        );
+        assert_eq!(
+            dns_response.answers(RecordType::Ptr).collect::<Vec<_>>(),
+            ["crawl-66-249-66-1.googlebot.com."]
+        );
+        assert_eq!(dns_response.answer.len(), 3);
+        assert_eq!(dns_response.min_ttl(), Some(300));
+    }
+
+    #[test]
+    fn escaped_names_round_trip() {
+        let name = r"crawl\.googlebot\.com.example\092.";
+        let query = encode_query(name, RecordType::Ptr).unwrap();
+        let message = respond(query, 0, &[(RecordType::Ptr.code(), &QUESTION_NAME)]);
+        let dns_response = decode_response(&message).unwrap();
+        assert_eq!(
+            dns_response.answers(RecordType::Ptr).collect::<Vec<_>>(),
+            [r"crawl\.googlebot\.com.example\\."]
+        );
+    }
+
+    #[test]
+    fn decodes_nxdomain() {
+        let query = encode_query("1.66.249.66.in-addr.arpa", RecordType::Ptr).unwrap();
+        let dns_response = decode_response(&respond(query, 3, &[])).unwrap();
+        assert!(dns_response.is_nxdomain());
+        assert!(dns_response.answer.is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        let query = encode_query("example.com", RecordType::A).unwrap();
+        // A query is not a response.
+        assert!(decode_response(&query).is_none());
+
+        let message = respond(query.clone(), 0, &[(RecordType::A.code(), &[192, 0, 2, 1])]);
+        assert!(decode_response(&message[..message.len() - 1]).is_none());
+
+        // An A record must hold 4 bytes.
+        let message = respond(query.clone(), 0, &[(RecordType::A.code(), &[192, 0, 2])]);
+        assert!(decode_response(&message).is_none());
+
+        // A compression pointer to itself never ends.
+        let pointer_loop = [0xc0, 0];
+        let mut message = respond(query, 0, &[(RecordType::Ptr.code(), &[0, 0])]);
+        let data_start = message.len() - 2;
+        message[data_start..].copy_from_slice(&pointer_loop);
+        message[data_start + 1] = data_start as u8;
+        assert!(decode_response(&message).is_none());
+    }
+}
@@ -0,0 +1,284 @@
+use serde::Deserialize;
+use std::net::IpAddr;
+
+/// The DNS response code of a successful query.
+const RCODE_NOERROR: u16 = 0;
+
+/// The DNS response code of a query the resolver failed to answer.
+const RCODE_SERVFAIL: u16 = 2;
+
+/// The DNS response code of a query for a name that does not exist.
+pub(crate) const RCODE_NXDOMAIN: u16 = 3;
+
+/// A DNS resource record type this service queries.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum RecordType {
+    /// An IPv4 address.
+    A,
+    /// An IPv6 address.
+    Aaaa,
+    /// A domain name pointer, used for reverse lookups.
+    Ptr,
+}
+
+impl RecordType {
+    /// The name of the record type, e.g. `AAAA`.
+    pub fn name(self) -> &'static str {
+        match self {
+            RecordType::A => "A",
+            RecordType::Aaaa => "AAAA",
+            RecordType::Ptr => "PTR",
+        }
+    }
+
+    /// The numeric code of the record type, e.g. 28 for AAAA.
+    pub fn code(self) -> u16 {
+        match self {
+            RecordType::A => 1,
+            RecordType::Aaaa => 28,
+            RecordType::Ptr => 12,
+        }
+    }
+}
+
+/// A response of the Google DNS-over-HTTPS JSON API,
+/// see <https://developers.google.com/speed/public-dns/docs/doh/json>,
+/// or a DNS message decoded by [`crate::dns_message::decode_response`].
+#[derive(Deserialize, Clone, Debug)]
+pub struct DnsResponse {
+    /// The name of the resolver that answered, once the response is parsed.
+    #[serde(skip)]
+    pub resolver: Option<&'static str>,
+    /// The DNS response code, e.g. 0 for NOERROR or 3 for NXDOMAIN.
+    #[serde(rename = "Status")]
+    pub status: u16,
+    /// Whether the response was truncated.
+    #[serde(rename = "TC", default)]
+    pub truncated: bool,
+    /// The answer section, which may also hold records of other types, such as CNAMEs.
+    #[serde(rename = "Answer", default)]
+    pub answer: Vec<DnsAnswer>,
+}
+
+/// A resource record in the answer section of a [`DnsResponse`].
+#[derive(Deserialize, Clone, Debug)]
+pub struct DnsAnswer {
+    /// The numeric code of the record type.
+    #[serde(rename = "type")]
+    pub record_type: u16,
+    /// How long, in seconds, the record may be cached.
+    #[serde(rename = "TTL")]
+    pub ttl: u32,
+    /// The record data: an IP address for A and AAAA records, a domain for PTR and CNAME records.
+    pub data: String,
+}
+
+impl DnsResponse {
+    /// Whether the queried name does not exist.
+    pub fn is_nxdomain(&self) -> bool {
+        self.status == RCODE_NXDOMAIN
+    }
+
+    /// Iterate over the data of every answer of the given record type,
+    /// skipping any other records (such as CNAMEs) in the answer section.
+    pub fn answers(&self, record_type: RecordType) -> impl Iterator<Item = &str> {
+        self.answer
+            .iter()
+            .filter(move |answer| answer.record_type == record_type.code())
+            .map(|answer| answer.data.as_str())
+    }
+
+    /// The lowest TTL of the records in the answer section, if it has any.
+    pub fn min_ttl(&self) -> Option<u32> {
+        self.answer.iter().map(|answer| answer.ttl).min()
+    }
+
+    /// Parse a response of the Google DNS JSON API.
+    pub fn from_json(body: &str) -> Option<DnsResponse> {
+        serde_json::from_str(body).ok()
+    }
+
+    /// Check that the response answers the query, even if only to say the name does not exist.
+    pub fn check(self) -> Result<DnsResponse, DnsError> {
+        match self.status {
+            RCODE_NOERROR | RCODE_NXDOMAIN => {}
+            RCODE_SERVFAIL => return Err(DnsError::ServerFailure),
+            _ => return Err(DnsError::ErrorStatus),
+        }
+        if self.truncated && self.answer.is_empty() {
+            return Err(DnsError::Truncated);
+        }
+        Ok(self)
+    }
+}
+
+/// Why a DNS query did not produce a usable response.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DnsError {
+    /// The resolver could not be reached, or answered with a server error status.
+    Unavailable,
+    /// The resolver answered with a client error status.
+    Rejected,
+    /// The resolver did not answer in time.
+    Timeout,
+    /// The resolver's response could not be parsed.
+    InvalidResponse,
+    /// The response was truncated before any answer.
+    Truncated,
+    /// The resolver failed to answer the query (SERVFAIL).
+    ServerFailure,
+    /// The resolver answered with another error response code, such as REFUSED.
+    ErrorStatus,
+    /// The name to query could not be encoded into a DNS message.
+    InvalidName,
+    /// The budget of DNS requests of this service is spent, so the query was not sent.
+    RateLimited,
+}
+
+impl DnsError {
+    /// Every error, e.g. to enumerate the metric labels of their codes.
+    pub const ALL: [DnsError; 9] = [
+        DnsError::Unavailable,
+        DnsError::Rejected,
+        DnsError::Timeout,
+        DnsError::InvalidResponse,
+        DnsError::Truncated,
+        DnsError::ServerFailure,
+        DnsError::ErrorStatus,
+        DnsError::InvalidName,
+        DnsError::RateLimited,
+    ];
+
+    /// The stable, machine-readable code of the error, e.g. as a metric label.
+    pub fn code(self) -> &'static str {
+        match self {
+            DnsError::Unavailable => "unavailable",
+            DnsError::Rejected => "rejected",
+            DnsError::Timeout => "timeout",
+            DnsError::InvalidResponse => "invalid_response",
+            DnsError::Truncated => "truncated",
+            DnsError::ServerFailure => "server_failure",
+            DnsError::ErrorStatus => "error_status",
+            DnsError::InvalidName => "invalid_name",
+            DnsError::RateLimited => "rate_limited",
+        }
+    }
+
+    /// Whether the query may succeed if it is sent again to the same resolver.
+    pub fn is_retryable(self) -> bool {
+        matches!(
+            self,
+            DnsError::Unavailable | DnsError::Timeout | DnsError::ServerFailure
+        )
+    }
+}
+
+/// Build the name to look up the PTR record of `ip`: `d.c.b.a.in-addr.arpa` for IPv4,
+/// and the nibble-reversed `*.ip6.arpa` name for IPv6.
+pub fn reverse_lookup_name(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ipv4) => {
+            let ipv4_octets = ipv4.octets();
+            format!(
+                "{}.{}.{}.{}.in-addr.arpa",
+                ipv4_octets[3], ipv4_octets[2], ipv4_octets[1], ipv4_octets[0],
+            )
+        }
+        IpAddr::V6(ipv6) => {
+            let mut name = String::with_capacity(72);
+            for octet in ipv6.octets().iter().rev() {
+                name.push_str(&format!("{:x}.{:x}.", octet & 0x0f, octet >> 4));
+            }
+            name.push_str("ip6.arpa");
+            name
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reverse_lookup_name_of_ipv4() {
+        assert_eq!(
+            reverse_lookup_name("66.249.66.1".parse().unwrap()),
+            "1.66.249.66.in-addr.arpa"
+        );
+    }
+
+    #[test]
+    fn reverse_lookup_name_of_ipv6() {
+        assert_eq!(
+            reverse_lookup_name("2001:db8::567:89ab".parse().unwrap()),
+            "b.a.9.8.7.6.5.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.0.8.b.d.0.1.0.0.2.ip6.arpa"
+        );
+    }
+
+    #[test]
+    fn parses_a_json_api_response() {
+        let dns_response = DnsResponse::from_json(
+            r#"{
+                "Status": 0,
+                "TC": false,
+                "Question": [{"name": "1.66.249.66.in-addr.arpa.", "type": 12}],
+                "Answer": [
+                    {"name": "1.66.249.66.in-addr.arpa.", "type": 5, "TTL": 60, "data": "alias."},
+                    {"name": "alias.", "type": 12, "TTL": 300, "data": "crawl-66-249-66-1.googlebot.com."}
+                ]
+            }"#,
+        )
+        .unwrap()
+        .check()
+        .unwrap();
+        assert!(!dns_response.is_nxdomain());
+        assert_eq!(
+            dns_response.answers(RecordType::Ptr).collect::<Vec<_>>(),
+            ["crawl-66-249-66-1.googlebot.com."]
+        );
+        assert_eq!(dns_response.min_ttl(), Some(60));
+    }
+
+    #[test]
+    fn nxdomain_answers_the_query() {
+        let dns_response = DnsResponse::from_json(r#"{"Status": 3}"#)
+            .unwrap()
+            .check()
+            .unwrap();
+        assert!(dns_response.is_nxdomain());
+        assert_eq!(dns_response.min_ttl(), None);
+    }
+
+    #[test]
+    fn rejects_invalid_json() {
+        assert!(DnsResponse::from_json("<html></html>").is_none());
+        assert!(DnsResponse::from_json(r#"{"Answer": []}"#).is_none());
+    }
+
+    #[test]
+    fn check_rejects_failed_queries() {
+        let check = |body: &str| DnsResponse::from_json(body).unwrap().check().map(|_| ());
+        assert_eq!(check(r#"{"Status": 2}"#), Err(DnsError::ServerFailure));
+        assert_eq!(check(r#"{"Status": 5}"#), Err(DnsError::ErrorStatus));
+        assert_eq!(
+            check(r#"{"Status": 0, "TC": true}"#),
+            Err(DnsError::Truncated)
+        );
+        assert_eq!(
+            check(
+                r#"{"Status": 0, "TC": true, "Answer": [{"type": 1, "TTL": 60, "data": "192.0.2.1"}]}"#
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn retryable_errors() {
+        assert!(DnsError::Timeout.is_retryable());
+        assert!(DnsError::Unavailable.is_retryable());
+        assert!(DnsError::ServerFailure.is_retryable());
+        assert!(!DnsError::Rejected.is_retryable());
+        assert!(!DnsError::InvalidResponse.is_retryable());
+        assert!(!DnsError::RateLimited.is_retryable());
+    }
+}
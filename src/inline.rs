@@ -0,0 +1,96 @@
+use crate::client_ip::client_ip;
+use crate::config::config;
+use crate::{json_response, log_verification, token, verify_ip_cached};
+use crawler_verification::crawlers::Crawler;
+use crawler_verification::outcome::Outcome;
+use fastly::http::request::SendErrorCause;
+use fastly::http::StatusCode;
+use fastly::{Request, Response};
+use std::time::{Duration, Instant};
+
+/// The name of a backend server associated with this service.
+/// In inline mode, every client request is forwarded to it.
+const ORIGIN: &str = "origin_5";
+
+/// How long a tarpitted request is held before it is forwarded.
+const TARPIT_DELAY: Duration = Duration::from_secs(10);
+
+/// What to do with a request whose User-Agent claims to be a crawler it could not be verified as.
+enum Policy {
+    /// Forward the request, with the lookup result in the verification header.
+    Annotate,
+    /// Respond with a 403 and the outcome of the lookup instead of forwarding the request.
+    Block,
+    /// Hold the request for [`TARPIT_DELAY`], then forward it like [`Policy::Annotate`].
+    Tarpit,
+}
+
+impl Policy {
+    fn parse(policy: &str) -> Option<Policy> {
+        match policy {
+            "annotate" => Some(Policy::Annotate),
+            "block" => Some(Policy::Block),
+            "tarpit" => Some(Policy::Tarpit),
+            _ => None,
+        }
+    }
+}
+
+/// Forward a client request to the origin, verifying the client IP on the way if the
+/// User-Agent claims to be a crawler.
+pub fn handle_inline_request(mut req: Request) -> Result<Response, Outcome> {
+    // Only this service gets to say whether a request came from a crawler.
+    let verified_header = &config().verified_header;
+    req.remove_header(verified_header);
+    req.remove_header(&config().token_header);
+
+    let claimed_crawler = req
+        .get_header("user-agent")
+        .and_then(|user_agent| user_agent.to_str().ok())
+        .and_then(Crawler::find_by_user_agent);
+    let (claimed_crawler, client_ip) = match (claimed_crawler, client_ip(&req)) {
+        (Some(claimed_crawler), Some((client_ip, _))) => (claimed_crawler, client_ip),
+        _ => return send_to_origin(req),
+    };
+
+    // Lookup errors fail open: the request is forwarded, annotated with `error`.
+    let started = Instant::now();
+    let (outcome, cache_status) = verify_ip_cached(client_ip, &[claimed_crawler], false);
+    let outcome = outcome.check_claim(Some(claimed_crawler));
+    log_verification(client_ip, &outcome, &cache_status, started.elapsed());
+    let result = outcome.result();
+    req.set_header(verified_header, result);
+    if result != "error" {
+        if let Some(token) = token::issue(client_ip, Some(claimed_crawler.name), result) {
+            req.set_header(&config().token_header, token);
+        }
+    }
+
+    if result == "yes" || result == "error" {
+        return send_to_origin(req);
+    }
+
+    match Policy::parse(&config().inline_policy).unwrap_or(Policy::Annotate) {
+        Policy::Annotate => send_to_origin(req),
+        Policy::Block => {
+            // The lookup response body, so that clients see a single error format.
+            let (_, body_json) = outcome.into_status_and_json();
+            Ok(json_response(StatusCode::FORBIDDEN, &body_json)
+                .with_header(verified_header, result))
+        }
+        Policy::Tarpit => {
+            std::thread::sleep(TARPIT_DELAY);
+            send_to_origin(req)
+        }
+    }
+}
+
+/// Forward `req` to the origin.
+fn send_to_origin(req: Request) -> Result<Response, Outcome> {
+    req.send(ORIGIN).map_err(|error| match error.root_cause() {
+        SendErrorCause::HttpResponseTimeout | SendErrorCause::ConnectionTimeout => {
+            Outcome::OriginTimeout
+        }
+        _ => Outcome::OriginUnavailable,
+    })
+}
@@ -0,0 +1,118 @@
+use crate::config::config;
+use crate::{dns, latency_budget};
+use crawler_verification::crawlers::{Crawler, IpRangeList};
+use crawler_verification::ip_ranges::{published_prefixes, IpRanges};
+use fastly::cache::simple::{self, CacheEntry};
+use fastly::{Request, Response};
+use serde_json::Value;
+use std::time::Duration;
+
+/// How long, in seconds, the ranges of a published IP range list are cached at the edge.
+const IP_RANGES_TTL: u32 = 86400;
+
+/// How long, in seconds, a list that could not be fetched or parsed is cached at the edge as
+/// an empty one, so that verifications do not keep waiting on a list that is down.
+const FAILED_LIST_TTL: u32 = 60;
+
+/// The IP ranges published for `crawlers`.
+///
+/// The ranges of every list are cached at the edge, one per line, so only the first request
+/// after the cache expires fetches and parses the list. The lists missing from the cache are
+/// fetched concurrently, within the latency budget. A list that cannot be fetched or parsed
+/// in time is skipped, so that it does not keep the other lists from matching, and cached as
+/// empty for [`FAILED_LIST_TTL`].
+pub fn fetch_ip_ranges(crawlers: &[&'static Crawler]) -> IpRanges {
+    let mut ip_ranges = IpRanges::default();
+    let mut fetching = Vec::new();
+    let mut pending_requests = Vec::new();
+    for crawler in crawlers {
+        for list in crawler.ip_range_lists {
+            if let Some(prefixes) = cached_prefixes(list) {
+                for prefix in prefixes.lines() {
+                    ip_ranges.insert(prefix, (*crawler, list.name));
+                }
+            } else if !latency_budget::is_exhausted() {
+                match Request::get(list.url).send_async(backend(list)) {
+                    Ok(pending_request) => {
+                        fetching.push((*crawler, list));
+                        pending_requests.push(pending_request);
+                    }
+                    Err(_) => cache_prefixes(list, "", FAILED_LIST_TTL),
+                }
+            }
+        }
+    }
+
+    while !pending_requests.is_empty() {
+        let (beresp, remaining) = match latency_budget::select_within_budget(pending_requests) {
+            Some(selected) => selected,
+            None => break,
+        };
+        pending_requests = remaining;
+        let (url, prefixes) = match beresp.ok().and_then(parse_list) {
+            Some(parsed) => parsed,
+            None => continue,
+        };
+        if let Some(index) = fetching.iter().position(|(_, list)| list.url == url) {
+            let (crawler, list) = fetching.swap_remove(index);
+            cache_prefixes(list, &prefixes, IP_RANGES_TTL);
+            for prefix in prefixes.lines() {
+                ip_ranges.insert(prefix, (crawler, list.name));
+            }
+        }
+    }
+
+    // Whatever is left failed, or did not answer within the latency budget.
+    for (_, list) in fetching {
+        cache_prefixes(list, "", FAILED_LIST_TTL);
+    }
+    ip_ranges
+}
+
+/// The backend to fetch `list` from: one created on the fly with the DNS timeouts,
+/// if any are set and it could be created, or else the list's own.
+fn backend(list: &IpRangeList) -> String {
+    let timed_backend = format!("{}_timed", list.backend);
+    match &config().dns_timeouts {
+        Some(dns_timeouts) if dns::create_timed_backend(&timed_backend, list.url, dns_timeouts) => {
+            timed_backend
+        }
+        _ => list.backend.to_string(),
+    }
+}
+
+/// The URL of the list `beresp` answers, and the ranges of the list, one per line,
+/// unless the response is not a successful one with a JSON body.
+fn parse_list(mut beresp: Response) -> Option<(String, String)> {
+    let url = beresp.get_backend_request()?.get_url_str().to_string();
+    if !beresp.get_status().is_success() {
+        return None;
+    }
+    let list_data: Value = serde_json::from_str(&beresp.take_body_str()).ok()?;
+    let prefixes = published_prefixes(&list_data)
+        .collect::<Vec<_>>()
+        .join("\n");
+    Some((url, prefixes))
+}
+
+/// The edge cache key of the ranges of `list`.
+fn cache_key(list: &IpRangeList) -> String {
+    format!("ip_ranges:{}", list.url)
+}
+
+/// The cached ranges of `list`, one per line.
+fn cached_prefixes(list: &IpRangeList) -> Option<String> {
+    let body = simple::get(cache_key(list)).ok()??;
+    Some(body.into_string())
+}
+
+/// Cache the ranges of `list`, one per line, for `ttl` seconds.
+fn cache_prefixes(list: &IpRangeList, prefixes: &str, ttl: u32) {
+    // Caching is best-effort: if it fails, the next request just fetches the list again.
+    let _ = simple::get_or_set_with(cache_key(list), || {
+        Ok(CacheEntry {
+            value: prefixes.to_string().into(),
+            ttl: Duration::from_secs(ttl.into()),
+        })
+    });
+}
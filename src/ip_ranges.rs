@@ -0,0 +1,298 @@
+use crate::crawlers::Crawler;
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// The published range an IP address was found in.
+pub struct IpRangeMatch {
+    /// The crawler the range was published for.
+    pub crawler: &'static Crawler,
+    /// The name of the list the range was published in, e.g. `special-crawlers`.
+    pub list: &'static str,
+    /// The matching range in CIDR notation.
+    pub prefix: String,
+}
+
+/// The crawler and list a range was published in.
+pub type RangeSource = (&'static Crawler, &'static str);
+
+/// A set of CIDR ranges, each tagged with a value of type `T`, indexed by prefix length
+/// so that a lookup costs one hash probe per distinct prefix length rather than one
+/// comparison per range.
+pub struct IpRanges<T = RangeSource> {
+    v4: BTreeMap<u8, HashMap<u32, T>>,
+    v6: BTreeMap<u8, HashMap<u128, T>>,
+}
+
+impl<T> Default for IpRanges<T> {
+    fn default() -> Self {
+        IpRanges {
+            v4: BTreeMap::new(),
+            v6: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T: Copy> IpRanges<T> {
+    /// Add a range in CIDR notation, tagged with `value`. A bare IP address is a range
+    /// of its own. Adding a range again replaces its value.
+    ///
+    /// Returns `None` if `prefix` is not a valid CIDR range.
+    pub fn insert(&mut self, prefix: &str, value: T) -> Option<()> {
+        let (addr, len) = match prefix.split_once('/') {
+            Some((addr, len)) => (addr.parse::<IpAddr>().ok()?, len.parse::<u8>().ok()?),
+            None => match prefix.parse::<IpAddr>().ok()? {
+                addr @ IpAddr::V4(_) => (addr, 32),
+                addr @ IpAddr::V6(_) => (addr, 128),
+            },
+        };
+        match addr {
+            IpAddr::V4(addr) if len <= 32 => {
+                let network = u32::from(addr) & mask_v4(len);
+                self.v4.entry(len).or_default().insert(network, value);
+            }
+            IpAddr::V6(addr) if len <= 128 => {
+                let network = u128::from(addr) & mask_v6(len);
+                self.v6.entry(len).or_default().insert(network, value);
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    /// Find the most specific range containing `ip`, returning its value and the range
+    /// in CIDR notation.
+    pub fn find(&self, ip: IpAddr) -> Option<(T, String)> {
+        match ip {
+            IpAddr::V4(addr) => {
+                let addr = u32::from(addr);
+                self.v4.iter().rev().find_map(|(len, networks)| {
+                    let network = addr & mask_v4(*len);
+                    networks.get(&network).map(|value| {
+                        let prefix = format!("{}/{}", Ipv4Addr::from(network), len);
+                        (*value, prefix)
+                    })
+                })
+            }
+            IpAddr::V6(addr) => {
+                let addr = u128::from(addr);
+                self.v6.iter().rev().find_map(|(len, networks)| {
+                    let network = addr & mask_v6(*len);
+                    networks.get(&network).map(|value| {
+                        let prefix = format!("{}/{}", Ipv6Addr::from(network), len);
+                        (*value, prefix)
+                    })
+                })
+            }
+        }
+    }
+}
+
+impl IpRanges {
+    /// Find the most specific published range containing `ip`.
+    pub fn lookup(&self, ip: IpAddr) -> Option<IpRangeMatch> {
+        self.find(ip).map(|((crawler, list), prefix)| IpRangeMatch {
+            crawler,
+            list,
+            prefix,
+        })
+    }
+}
+
+/// The ranges of a published list, e.g.
+/// `{"prefixes": [{"ipv4Prefix": "66.249.64.0/27"}, {"ipv6Prefix": "2001:4860:4801:10::/64"}]}`.
+/// Entries without a range are skipped, and the ranges are not checked.
+pub fn published_prefixes(list_data: &Value) -> impl Iterator<Item = &str> {
+    list_data["prefixes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|prefix| {
+            prefix["ipv4Prefix"]
+                .as_str()
+                .or_else(|| prefix["ipv6Prefix"].as_str())
+        })
+}
+
+/// The netmask of an IPv4 prefix of length `len`.
+fn mask_v4(len: u8) -> u32 {
+    u32::MAX.checked_shl(32 - u32::from(len)).unwrap_or(0)
+}
+
+/// The netmask of an IPv6 prefix of length `len`.
+fn mask_v6(len: u8) -> u128 {
+    u128::MAX.checked_shl(128 - u32::from(len)).unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawlers::CRAWLERS;
+
+    fn ranges(prefixes: &[(&str, u8)]) -> IpRanges<u8> {
+        let mut ranges = IpRanges::default();
+        for (prefix, value) in prefixes {
+            ranges.insert(prefix, *value).unwrap();
+        }
+        ranges
+    }
+
+    fn find(ranges: &IpRanges<u8>, ip: &str) -> Option<(u8, String)> {
+        ranges.find(ip.parse().unwrap())
+    }
+
+    #[test]
+    fn finds_the_range_containing_an_ip() {
+        let ranges = ranges(&[("66.249.64.0/27", 1), ("2001:4860:4801:10::/64", 2)]);
+        assert_eq!(
+            find(&ranges, "66.249.64.31"),
+            Some((1, "66.249.64.0/27".to_string()))
+        );
+        assert_eq!(find(&ranges, "66.249.64.32"), None);
+        assert_eq!(
+            find(&ranges, "2001:4860:4801:10::1"),
+            Some((2, "2001:4860:4801:10::/64".to_string()))
+        );
+        assert_eq!(find(&ranges, "2001:4860:4801:11::1"), None);
+    }
+
+    #[test]
+    fn ipv4_and_ipv6_ranges_are_apart() {
+        let ranges = ranges(&[("0.0.0.0/0", 1)]);
+        assert_eq!(
+            find(&ranges, "203.0.113.7").map(|(value, _)| value),
+            Some(1)
+        );
+        assert_eq!(find(&ranges, "::ffff:203.0.113.7"), None);
+        assert_eq!(find(&ranges, "2001:db8::1"), None);
+    }
+
+    #[test]
+    fn zero_length_prefixes_contain_every_ip() {
+        let ranges = ranges(&[("0.0.0.0/0", 1), ("::/0", 2)]);
+        assert_eq!(
+            find(&ranges, "255.255.255.255"),
+            Some((1, "0.0.0.0/0".to_string()))
+        );
+        assert_eq!(find(&ranges, "ffff::1"), Some((2, "::/0".to_string())));
+    }
+
+    #[test]
+    fn full_length_prefixes_and_bare_ips_contain_one_ip() {
+        let ranges = ranges(&[
+            ("192.0.2.1/32", 1),
+            ("192.0.2.2", 2),
+            ("2001:db8::1/128", 3),
+            ("2001:db8::2", 4),
+        ]);
+        assert_eq!(
+            find(&ranges, "192.0.2.1"),
+            Some((1, "192.0.2.1/32".to_string()))
+        );
+        assert_eq!(
+            find(&ranges, "192.0.2.2"),
+            Some((2, "192.0.2.2/32".to_string()))
+        );
+        assert_eq!(find(&ranges, "192.0.2.3"), None);
+        assert_eq!(
+            find(&ranges, "2001:db8::1"),
+            Some((3, "2001:db8::1/128".to_string()))
+        );
+        assert_eq!(
+            find(&ranges, "2001:db8::2"),
+            Some((4, "2001:db8::2/128".to_string()))
+        );
+        assert_eq!(find(&ranges, "2001:db8::3"), None);
+    }
+
+    #[test]
+    fn the_most_specific_range_wins() {
+        let ranges = ranges(&[("10.0.0.0/8", 8), ("10.1.0.0/16", 16), ("10.1.2.0/24", 24)]);
+        assert_eq!(
+            find(&ranges, "10.1.2.3"),
+            Some((24, "10.1.2.0/24".to_string()))
+        );
+        assert_eq!(
+            find(&ranges, "10.1.3.3"),
+            Some((16, "10.1.0.0/16".to_string()))
+        );
+        assert_eq!(
+            find(&ranges, "10.2.3.4"),
+            Some((8, "10.0.0.0/8".to_string()))
+        );
+    }
+
+    #[test]
+    fn a_range_is_normalized_to_its_network() {
+        let ranges = ranges(&[("192.0.2.77/24", 1), ("2001:db8::1/32", 2)]);
+        assert_eq!(
+            find(&ranges, "192.0.2.1"),
+            Some((1, "192.0.2.0/24".to_string()))
+        );
+        assert_eq!(
+            find(&ranges, "2001:db8:ffff::1"),
+            Some((2, "2001:db8::/32".to_string()))
+        );
+    }
+
+    #[test]
+    fn a_range_added_again_takes_the_new_value() {
+        // As the deny list is added after the allow list, it wins on the same range.
+        let ranges = ranges(&[("192.0.2.0/24", 1), ("192.0.2.0/24", 2)]);
+        assert_eq!(
+            find(&ranges, "192.0.2.1"),
+            Some((2, "192.0.2.0/24".to_string()))
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_ranges() {
+        let mut ranges = IpRanges::default();
+        for prefix in [
+            "192.0.2.0/33",
+            "2001:db8::/129",
+            "192.0.2.0/",
+            "192.0.2.0/-1",
+            "192.0.2.0/x",
+            "192.0.2/24",
+            "not-an-ip",
+            "",
+        ] {
+            assert_eq!(ranges.insert(prefix, ()), None, "{}", prefix);
+        }
+        assert!(ranges.find("192.0.2.1".parse().unwrap()).is_none());
+    }
+
+    #[test]
+    fn reads_the_ranges_of_a_published_list() {
+        let googlebot = &CRAWLERS[0];
+        let list_data = serde_json::json!({
+            "creationTime": "2024-01-01T00:00:00.000000",
+            "prefixes": [
+                {"ipv4Prefix": "66.249.64.0/27"},
+                {"ipv6Prefix": "2001:4860:4801:10::/64"},
+                {"ipv4Prefix": "not-a-range"},
+                {"somethingElse": "192.0.2.0/24"},
+            ],
+        });
+        assert_eq!(
+            published_prefixes(&list_data).collect::<Vec<_>>(),
+            ["66.249.64.0/27", "2001:4860:4801:10::/64", "not-a-range"]
+        );
+        assert_eq!(published_prefixes(&serde_json::json!({})).count(), 0);
+
+        let mut ranges = IpRanges::default();
+        for prefix in published_prefixes(&list_data) {
+            ranges.insert(prefix, (googlebot, "googlebot"));
+        }
+
+        let ip_range = ranges.lookup("66.249.64.1".parse().unwrap()).unwrap();
+        assert_eq!(ip_range.crawler.name, "googlebot");
+        assert_eq!(ip_range.list, "googlebot");
+        assert_eq!(ip_range.prefix, "66.249.64.0/27");
+        assert!(ranges
+            .lookup("2001:4860:4801:10::1".parse().unwrap())
+            .is_some());
+        assert!(ranges.lookup("192.0.2.1".parse().unwrap()).is_none());
+    }
+}
@@ -0,0 +1,69 @@
+use crate::config::config;
+use fastly::http::request::{select, PendingRequest, PollResult, SendError};
+use fastly::Response;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+/// How long to sleep between polls of the requests in flight, while waiting for one of them
+/// within the latency budget.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+/// When this service started handling the client request.
+static STARTED: OnceLock<Instant> = OnceLock::new();
+
+/// Start the clock of the client request's latency budget.
+pub fn start() {
+    STARTED.get_or_init(Instant::now);
+}
+
+/// Whether the client request's latency budget has run out.
+pub fn is_exhausted() -> bool {
+    remaining() == Some(Duration::ZERO)
+}
+
+/// Sleep for `duration`, unless that would overrun the latency budget.
+/// Returns whether it slept.
+pub fn sleep_within_budget(duration: Duration) -> bool {
+    if remaining().is_some_and(|remaining| remaining <= duration) {
+        return false;
+    }
+    std::thread::sleep(duration);
+    true
+}
+
+/// What is left of the client request's latency budget, if there is one.
+fn remaining() -> Option<Duration> {
+    let latency_budget = config().latency_budget?;
+    let started = STARTED.get_or_init(Instant::now);
+    Some(latency_budget.saturating_sub(started.elapsed()))
+}
+
+/// Wait for the first of `pending_requests` to complete, like [`select`], unless the latency
+/// budget runs out first, in which case the requests are abandoned and `None` is returned.
+pub fn select_within_budget(
+    mut pending_requests: Vec<PendingRequest>,
+) -> Option<(Result<Response, SendError>, Vec<PendingRequest>)> {
+    if config().latency_budget.is_none() {
+        return Some(select(pending_requests));
+    }
+
+    loop {
+        let mut still_pending = Vec::with_capacity(pending_requests.len());
+        let mut polled = pending_requests.into_iter();
+        while let Some(pending_request) = polled.next() {
+            match pending_request.poll() {
+                PollResult::Done(beresp) => {
+                    still_pending.extend(polled);
+                    return Some((beresp, still_pending));
+                }
+                PollResult::Pending(pending_request) => still_pending.push(pending_request),
+            }
+        }
+
+        if still_pending.is_empty() || is_exhausted() {
+            return None;
+        }
+        pending_requests = still_pending;
+        std::thread::sleep(POLL_INTERVAL);
+    }
+}
@@ -0,0 +1,20 @@
+//! The verification logic that does not depend on the Compute runtime: building reverse lookup
+//! names, parsing DNS answers, matching PTR records to crawlers and IPs to CIDR ranges,
+//! deciding the [`Outcome`] of a lookup, describing it as JSON, signing verification tokens,
+//! and reading the API key of a request. DNS queries go through the [`Resolve`] trait, and the signing key and the clock are
+//! passed in, so the logic runs, and is tested, on any host.
+//!
+//! [`Outcome`]: outcome::Outcome
+//! [`Resolve`]: verify::Resolve
+
+pub mod api_keys;
+pub mod batch_entries;
+pub mod crawlers;
+pub mod dns_message;
+pub mod dns_response;
+pub mod ip_ranges;
+#[cfg(test)]
+mod mock;
+pub mod outcome;
+pub mod signed_token;
+pub mod verify;
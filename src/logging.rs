@@ -0,0 +1,30 @@
+use crate::config::config;
+use fastly::log::Endpoint;
+use serde_json::Value;
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Write `event` as a line of JSON to the configured log endpoint, stamped with the time
+/// in milliseconds since the Unix epoch, if logging is on and the event is sampled.
+///
+/// Logging is best-effort: a missing endpoint or a failed write never fails a request.
+pub fn log_event(event: Value) {
+    let config = config();
+    if !config.logging || rand::random::<f64>() >= config.log_sample_rate {
+        return;
+    }
+    log_unsampled(event);
+}
+
+/// Write an event to the log endpoint whatever the `logging` and `log_sample_rate`,
+/// e.g. for events every one of which counts, like metrics.
+pub fn log_unsampled(mut event: Value) {
+    event["timestamp"] = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or(0)
+        .into();
+    if let Ok(mut endpoint) = Endpoint::try_from_name(&config().log_endpoint) {
+        let _ = writeln!(endpoint, "{}", event);
+    }
+}
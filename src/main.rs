@@ -1,146 +1,375 @@
+mod auth;
+mod batch;
+mod cache;
+mod client_ip;
+mod config;
+mod dns;
+mod inline;
+mod ip_range_lists;
+mod latency_budget;
+mod logging;
+mod metrics;
+mod rate_limit;
+mod token;
+
+use auth::Scope;
+use client_ip::{client_ip, IpSource};
+use config::{config, OverrideList};
+use crawler_verification::crawlers::{Crawler, AUTO_CRAWLER, CRAWLERS, DEFAULT_CRAWLER};
+use crawler_verification::outcome::Outcome;
+use crawler_verification::verify;
 use fastly::http::{header, Method, StatusCode};
-use fastly::{Error, Request, Response};
+use fastly::{Request, Response};
 use serde_json::Value;
 use std::collections::HashMap;
-use std::net::Ipv4Addr;
-
-/// The name of a backend server associated with this service.
-/// When configuring the backend using Fastly's UI, make sure it points to "dns.google.com".
-const DNS_RESOLVER: &str = "origin_0";
-
-/// The outcome of a lookup request.
-enum Outcome {
-    /// The client request had no query string.
-    MissingQueryString,
-    /// The client request had an invalid query string.
-    InvalidQueryString,
-    /// Google DNS failed.
-    GoogleDnsFailed,
-    /// The client request came from a googlebot.
-    IsGoogleBot { ptr_record: String },
-    /// The client request did not come from a googlebot.
-    NotGoogleBot { ptr_record: String },
-    /// No PTR Answer was found.
-    NoPtrAnswer,
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// The media type a client request asks for with its `Accept` header to get
+/// a [`SchemaVersion::V2`] lookup response.
+const V2_MEDIA_TYPE: &str = "application/vnd.crawler-verification.v2+json";
+
+/// Convert a client request's [`Outcome`] into a lookup response with the `version` schema,
+/// without any of the details of the lookup.
+fn outcome_response(outcome: Outcome, version: SchemaVersion) -> Response {
+    metrics::record_outcome(&outcome);
+    let result = outcome.result();
+    let retry_after = outcome.retry_after();
+    let (status, mut body_json) = outcome.into_status_and_json();
+    if version == SchemaVersion::V2 {
+        body_json["schema_version"] = 2.into();
+    }
+    let mut response = lookup_response(status, result, &body_json);
+    if let Some(retry_after) = retry_after {
+        response.set_header(header::RETRY_AFTER, retry_after.to_string());
+    }
+    if status == StatusCode::UNAUTHORIZED {
+        response.set_header(header::WWW_AUTHENTICATE, "Bearer");
+    }
+    response
 }
 
-/// Convert a lookup request's [`Outcome`] into an HTTP [`Response`].
-impl From<Outcome> for Response {
-    fn from(outcome: Outcome) -> Self {
-        use Outcome::*;
-        let (result, reason, status) = match outcome {
-            MissingQueryString => (
-                "error",
-                "Missing query string ?ip=a.b.c.d".to_string(),
-                StatusCode::BAD_REQUEST,
-            ),
-            InvalidQueryString => (
-                "error",
-                "Invalid query string ?ip=a.b.c.d".to_string(),
-                StatusCode::BAD_REQUEST,
-            ),
-            GoogleDnsFailed => (
-                "error",
-                "Google DNS failed".to_string(),
-                StatusCode::BAD_GATEWAY,
-            ),
-            IsGoogleBot { ptr_record } => (
-                "yes",
-                format!("Reverse lookup is {}", ptr_record),
-                StatusCode::OK,
-            ),
-            NotGoogleBot { ptr_record } => (
-                "no",
-                format!(
-                    "Reverse lookup is {}, not an *.google.com or *.googlebot.com domain.",
-                    ptr_record
-                ),
-                StatusCode::OK,
-            ),
-            NoPtrAnswer => (
-                "no",
-                "No PTR Answer for this reverse lookup.".to_string(),
-                StatusCode::OK,
-            ),
-        };
-        let body_json = serde_json::json!({
-            "result": result,
-            "reason": reason,
-        });
-
-        Response::from_status(status)
-            .with_header(header::CONTENT_TYPE, "application/json")
-            .with_header("x-googlebot-verified", result)
-            .with_body_json(&body_json)
-            .unwrap()
+/// The schema of a lookup response body.
+#[derive(Clone, Copy, PartialEq)]
+enum SchemaVersion {
+    /// `{result, code, reason}`, with the details of the lookup in `reason`,
+    /// plus `crawler`, `ip`, `ip_source` and `token` where they apply.
+    V1,
+    /// The v1 fields, plus a `schema_version` and every detail of the lookup in its own field.
+    V2,
+}
+
+impl SchemaVersion {
+    /// The schema a client request asks for: v2 on `/v2/verify` or with an `Accept` header
+    /// of [`V2_MEDIA_TYPE`], v1 otherwise.
+    fn requested(req: &Request) -> SchemaVersion {
+        let accepts_v2 = req
+            .get_header_all(header::ACCEPT)
+            .filter_map(|accept| accept.to_str().ok())
+            .flat_map(|accept| accept.split(','))
+            .any(|media_range| {
+                let media_type = media_range.split(';').next().unwrap_or_default();
+                media_type.trim().eq_ignore_ascii_case(V2_MEDIA_TYPE)
+            });
+        if req.get_path().starts_with("/v2/") || accepts_v2 {
+            SchemaVersion::V2
+        } else {
+            SchemaVersion::V1
+        }
     }
 }
 
-#[fastly::main]
-fn main(req: Request) -> Result<Response, Error> {
+/// Build a lookup response with the given `result`, unless the Config Store overrides the
+/// HTTP status of that result.
+fn lookup_response(status: StatusCode, result: &str, body_json: &Value) -> Response {
+    let status = config().status(result).unwrap_or(status);
+
+    json_response(status, body_json).with_header(&config().verified_header, result)
+}
+
+/// Build a response with a JSON body.
+fn json_response(status: StatusCode, body_json: &Value) -> Response {
+    Response::from_status(status)
+        .with_header(header::CONTENT_TYPE, "application/json")
+        .with_body(body_json.to_string())
+}
+
+fn main() {
+    latency_budget::start();
+    let req = Request::from_client();
+
+    // Every failure is an `Outcome`, so that clients always get a JSON body with a `code`.
     // Pattern match on the request method and path.
-    match (req.get_method(), req.get_path()) {
-        (&Method::GET, "/verify") => match handle_lookup_request(req) {
-            Ok(response) => Ok(response),
-            Err(error) => Ok(Response::from_status(StatusCode::BAD_REQUEST)
-                .with_body_text_plain(&format!("ERROR: {}", error))),
-        },
+    let response = match (req.get_method(), req.get_path()) {
+        // Served in every mode, so that an inline service can be monitored too, under a path
+        // that cannot clash with one of the origin.
+        (&Method::GET, metrics::NAMESPACED_PATH) => metrics::handle_metrics_request(req),
+        _ if config().mode == "inline" => inline::handle_inline_request(req),
+        (&Method::GET, "/metrics") => metrics::handle_metrics_request(req),
+        (&Method::GET, "/verify" | "/v2/verify") => {
+            let version = SchemaVersion::requested(&req);
+            rate_limit::check_ip(&req, 1)
+                .and_then(|()| auth::authenticate(&req, Scope::Verify))
+                .and_then(|api_key| rate_limit::check_api_key(api_key, 1))
+                .and_then(|()| handle_lookup_request(req, version))
+                .or_else(|outcome| Ok(outcome_response(outcome, version)))
+        }
+        (&Method::POST, "/verify/batch") => batch::handle_batch_request(req),
+        (&Method::GET, "/verify/token/check") => handle_token_check_request(req),
 
         // Catch all other requests and return a 404.
-        _ => Ok(Response::from_status(StatusCode::NOT_FOUND).with_body(
-            "Either the page you requested could not be found or the HTTP method is not GET.\n",
-        )),
-    }
+        _ => Err(Outcome::NotFound),
+    };
+    response
+        .unwrap_or_else(|outcome| outcome_response(outcome, SchemaVersion::V1))
+        .send_to_client();
+    // The metrics are only written once the client has its response, so as not to delay it.
+    metrics::flush();
 }
 
-fn handle_lookup_request(req: Request) -> Result<Response, Error> {
-    // extract the ip address from query string ?ip=value
-    let qs_params: HashMap<String, String> = req.get_query()?;
+fn handle_lookup_request(req: Request, version: SchemaVersion) -> Result<Response, Outcome> {
+    // extract the ip address from query string ?ip=value, or verify the client itself without it
+    let qs_params: HashMap<String, String> =
+        req.get_query().map_err(|_| Outcome::InvalidQueryString)?;
+    let ip = qs_params.get("ip").map(String::as_str);
 
-    let ip = match qs_params.get("ip") {
-        Some(ip) => ip,
-        // handle missing param
-        _ => {
-            return Ok(Outcome::MissingQueryString.into());
-        }
+    // the crawler the User-Agent from the query string ?ua=value claims to be, if any
+    let claimed_crawler = qs_params
+        .get("ua")
+        .and_then(|user_agent| Crawler::find_by_user_agent(user_agent));
+
+    // verify the crawler from the query string ?crawler=value, or identify it with ?crawler=auto
+    let crawler_name = match qs_params.get("crawler") {
+        Some(crawler_name) => crawler_name.as_str(),
+        None => claimed_crawler.map_or(DEFAULT_CRAWLER, |crawler| crawler.name),
+    };
+    let (candidates, auto_identify) = candidate_crawlers(crawler_name)?;
+
+    let (ip, ip_source) = match ip.map(str::parse::<IpAddr>) {
+        Some(Ok(ip)) => (ip, IpSource::Query),
+        Some(Err(_)) => return Err(Outcome::InvalidQueryString),
+        None => client_ip(&req).ok_or(Outcome::MissingQueryString)?,
     };
 
-    match ip.parse::<Ipv4Addr>() {
-        Ok(ipv4) => {
-            let ipv4_octets = ipv4.octets();
-            let uri = format!(
-                "https://dns.google.com/resolve?name={}.{}.{}.{}.in-addr.arpa&type=PTR",
-                ipv4_octets[3], ipv4_octets[2], ipv4_octets[1], ipv4_octets[0],
-            );
+    let started = Instant::now();
+    let (outcome, cache_status) = verify_ip_cached(ip, &candidates, auto_identify);
+    let ttl = cache::ttl(&outcome);
+
+    // A User-Agent claiming to be a crawler from an IP that is not that crawler's is spoofed.
+    let outcome = outcome.check_claim(claimed_crawler);
+    log_verification(ip, &outcome, &cache_status, started.elapsed());
 
-            let dns_request = Request::get(uri);
+    let result = outcome.result();
+    let ptr_records = outcome.ptr_records().to_vec();
+    let forward_confirmed = outcome.forward_confirmed();
+    let (status, mut body_json) = outcome.into_status_and_json();
+    body_json["ip"] = ip.to_string().into();
+    body_json["ip_source"] = ip_source.name().into();
+    // Tokens carry the crawler of the v1 body, so that they are the same across versions.
+    let token_crawler = body_json["crawler"].as_str().map(str::to_string);
+
+    if version == SchemaVersion::V2 {
+        let (age, resolver) = match cache_status {
+            CacheStatus::Hit { age } => (age, None),
+            CacheStatus::Miss { resolver } => (0, resolver),
+        };
+        body_json["schema_version"] = 2.into();
+        body_json["ip_version"] = match ip {
+            IpAddr::V4(_) => 4,
+            IpAddr::V6(_) => 6,
+        }
+        .into();
+        // Outcomes that name no crawler report the requested one, if any.
+        if body_json.get("crawler").is_none() {
+            body_json["crawler"] = (!auto_identify).then(|| candidates[0].name).into();
+        }
+        body_json["ptr_records"] = ptr_records.into();
+        body_json["forward_confirmed"] = forward_confirmed.into();
+        body_json["resolver"] = resolver.into();
+        // How much longer the verdict holds, in seconds.
+        body_json["ttl"] = ttl.map(|ttl| u64::from(ttl).saturating_sub(age)).into();
+        // When the verdict was reached, in seconds since the Unix epoch.
+        body_json["checked_at"] = cache::now().saturating_sub(age).into();
+        body_json["cached"] = matches!(cache_status, CacheStatus::Hit { .. }).into();
+        body_json["dns_attempts"] = dns::attempts().into();
+    }
+
+    // Only verdicts are signed, so that an origin never has to tell them from errors.
+    let token = match result {
+        "error" => None,
+        _ => token::issue(ip, token_crawler.as_deref(), result),
+    };
+    if let Some(token) = &token {
+        body_json["token"] = token.as_str().into();
+    }
 
-            let mut beresp = dns_request.send(DNS_RESOLVER)?;
-            if !beresp.get_status().is_success() {
-                return Ok(Outcome::GoogleDnsFailed.into());
+    let mut response = lookup_response(status, result, &body_json);
+    if let Some(token) = token {
+        response.set_header(&config().token_header, token);
+    }
+    response.set_header("x-dns-attempts", dns::attempts().to_string());
+    match cache_status {
+        CacheStatus::Hit { age } => {
+            response.set_header("x-cache", "HIT");
+            response.set_header(header::AGE, age.to_string());
+        }
+        CacheStatus::Miss { resolver } => {
+            response.set_header("x-cache", "MISS");
+            response.set_header(header::AGE, "0");
+            if let Some(resolver) = resolver {
+                response.set_header("x-dns-resolver", resolver);
             }
+        }
+    }
+    Ok(response)
+}
+
+/// Check the verification token from the query string ?token=value, or from the token header,
+/// and, with ?ip=value, whether it was issued for that IP.
+fn handle_token_check_request(req: Request) -> Result<Response, Outcome> {
+    let qs_params: HashMap<String, String> =
+        req.get_query().map_err(|_| Outcome::InvalidQueryString)?;
+    let token = qs_params
+        .get("token")
+        .map(String::as_str)
+        .or_else(|| {
+            req.get_header(&config().token_header)
+                .and_then(|token| token.to_str().ok())
+        })
+        .ok_or(Outcome::MissingToken)?;
+    let expected_ip = match qs_params.get("ip").map(|ip| ip.parse::<IpAddr>()) {
+        Some(Ok(ip)) => Some(ip),
+        Some(Err(_)) => return Err(Outcome::InvalidQueryString),
+        None => None,
+    };
+
+    let body_json = match token::check(token, expected_ip) {
+        Ok(claims) => serde_json::json!({
+            "valid": true,
+            "ip": claims.ip,
+            "crawler": claims.crawler,
+            "result": claims.result,
+            "exp": claims.exp,
+        }),
+        Err(error) => serde_json::json!({
+            "valid": false,
+            "reason": error.reason(),
+        }),
+    };
+
+    Ok(json_response(StatusCode::OK, &body_json))
+}
+
+/// The crawlers to verify an IP against for `crawler_name`, which is either the name of a
+/// crawler or `auto` for every known crawler, and whether the crawler is to be identified.
+fn candidate_crawlers(crawler_name: &str) -> Result<(Vec<&'static Crawler>, bool), Outcome> {
+    if crawler_name.eq_ignore_ascii_case(AUTO_CRAWLER) {
+        return Ok((CRAWLERS.iter().collect(), true));
+    }
+
+    match Crawler::find(crawler_name) {
+        Some(crawler) => Ok((vec![crawler], false)),
+        None => Err(Outcome::UnknownCrawler {
+            crawler: crawler_name.to_string(),
+        }),
+    }
+}
 
-            let beresp_body = beresp.take_body_str();
-            let dns_data: Value = serde_json::from_str(&beresp_body).unwrap();
-            let ptr_record = &dns_data["Answer"][0]["data"].as_str();
-
-            let is_googlebot_decision = match ptr_record {
-                Some(domain)
-                    if domain.ends_with(".google.com.") || domain.ends_with(".googlebot.com.") =>
-                {
-                    Outcome::IsGoogleBot {
-                        ptr_record: domain.to_string(),
-                    }
-                }
-                Some(domain) => Outcome::NotGoogleBot {
-                    ptr_record: domain.to_string(),
-                },
-
-                _ => Outcome::NoPtrAnswer,
-            };
-
-            Ok(is_googlebot_decision.into())
+/// Whether the outcome of a lookup request was served from the edge cache.
+enum CacheStatus {
+    /// The outcome was cached `age` seconds ago.
+    Hit { age: u64 },
+    /// The outcome was just looked up, with the last answer from `resolver`, if it took any.
+    Miss { resolver: Option<&'static str> },
+}
+
+/// Verify whether `ip` belongs to one of the `candidates` crawlers, through the edge cache.
+///
+/// Outcomes are cached per crawler selection, before any User-Agent claim is taken into account.
+fn verify_ip_cached(
+    ip: IpAddr,
+    candidates: &[&'static Crawler],
+    auto_identify: bool,
+) -> (Outcome, CacheStatus) {
+    if let Some(outcome) = override_outcome(ip) {
+        return (outcome, CacheStatus::Miss { resolver: None });
+    }
+
+    let cache_key = verify_cache_key(ip, candidates, auto_identify);
+
+    match cache::lookup(&cache_key) {
+        Some((outcome, age)) => (outcome, CacheStatus::Hit { age }),
+        None => {
+            let (outcome, resolver) = verify_ip(ip, candidates, auto_identify);
+            cache::insert(&cache_key, &outcome);
+            (outcome, CacheStatus::Miss { resolver })
         }
-        _ => Ok(Outcome::InvalidQueryString.into()),
     }
 }
+
+/// The outcome of verifying `ip` if it is within a range of the allow or deny list.
+///
+/// The lists take precedence over any lookup, and their outcomes are not cached,
+/// so that changes to them apply right away.
+fn override_outcome(ip: IpAddr) -> Option<Outcome> {
+    let (list, prefix) = config().override_list(ip)?;
+    Some(match list {
+        OverrideList::Allow => Outcome::AllowListed { prefix },
+        OverrideList::Deny => Outcome::DenyListed { prefix },
+    })
+}
+
+/// Log the verification of `ip`, which took `latency`, as a line of JSON, and count it
+/// in the metrics.
+fn log_verification(ip: IpAddr, outcome: &Outcome, cache_status: &CacheStatus, latency: Duration) {
+    let (cache, resolver) = match cache_status {
+        CacheStatus::Hit { .. } => ("hit", None),
+        CacheStatus::Miss { resolver } => ("miss", *resolver),
+    };
+    metrics::record_outcome(outcome);
+    metrics::record_verification(cache, latency);
+    logging::log_event(serde_json::json!({
+        "event": "verification",
+        "ip": ip.to_string(),
+        "outcome": outcome.code(),
+        "result": outcome.result(),
+        "verified_crawler": outcome.verified_crawler(),
+        "ptr_record": outcome.ptr_record(),
+        "forward_confirmed": outcome.forward_confirmed(),
+        "resolver": resolver,
+        "cache": cache,
+        // Every DNS request sent for the client request, so for a batch, for all its IPs.
+        "dns_attempts": dns::attempts(),
+        "latency_ms": latency.as_millis() as u64,
+        "api_key_id": auth::key_id(),
+    }));
+}
+
+/// The edge cache key of the outcome of verifying `ip` against the `candidates` crawlers.
+fn verify_cache_key(ip: IpAddr, candidates: &[&'static Crawler], auto_identify: bool) -> String {
+    let crawler_selection = if auto_identify {
+        AUTO_CRAWLER
+    } else {
+        candidates[0].name
+    };
+    format!("verify:{}:{}", crawler_selection, ip)
+}
+
+/// Verify whether `ip` belongs to one of the `candidates` crawlers.
+///
+/// If `auto_identify` is set, the candidates are every known crawler and a negative
+/// outcome does not name one. Also returns the resolver of the last DNS answer, if any.
+fn verify_ip(
+    ip: IpAddr,
+    candidates: &[&'static Crawler],
+    auto_identify: bool,
+) -> (Outcome, Option<&'static str>) {
+    verify::verify_ip(
+        &dns::ConfiguredResolvers,
+        &ip_range_lists::fetch_ip_ranges(candidates),
+        ip,
+        candidates,
+        auto_identify,
+        config().ptr_suffixes(),
+    )
+}
@@ -0,0 +1,331 @@
+use crate::config::config;
+use crate::dns::RESOLVERS;
+use crate::logging;
+use crawler_verification::dns_response::{DnsError, DnsResponse};
+use crawler_verification::outcome::Outcome;
+use fastly::erl::{CounterDuration, RateCounter};
+use fastly::http::{header, StatusCode};
+use fastly::{Request, Response, SecretStore};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Mutex, OnceLock};
+use std::time::Duration;
+
+/// The path metrics are served on in every mode, on top of `/metrics` in API mode,
+/// so that in inline mode the `/metrics` of the origin is passed on to it.
+pub const NAMESPACED_PATH: &str = "/_crawler_verification/metrics";
+
+/// The name of the rate counter the recent counts rendered by `/metrics` are kept in.
+/// Rate counters are incremented atomically, but only count the last minute, at each POP.
+const RATE_COUNTER: &str = "crawler_verification_metrics";
+
+/// How far back the counts rendered by `/metrics` go.
+const RECENT: CounterDuration = CounterDuration::SixtySecs;
+
+/// The name of the Secret Store holding the token `/metrics` is protected by.
+const SECRET_STORE: &str = "crawler_verification";
+
+/// The name of the `/metrics` token in the [`SECRET_STORE`] Secret Store.
+const METRICS_TOKEN: &str = "metrics_token";
+
+/// The prefix of every metric name.
+const PREFIX: &str = "crawler_verification";
+
+/// The upper bounds, in seconds, of the buckets of every latency histogram.
+const BUCKETS: [f64; 10] = [0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0];
+
+/// The media type of the OpenMetrics text format.
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+/// Every `result` of an [`Outcome`].
+const RESULTS: [&str; 4] = ["yes", "no", "spoofed", "error"];
+
+/// A metric family: a name, without the [`PREFIX`], and what it measures.
+struct Family {
+    name: &'static str,
+    help: &'static str,
+}
+
+/// Client requests, by the `code` and `result` of their outcome: one per IP for a batch,
+/// and one per request otherwise.
+const OUTCOMES: Family = Family {
+    name: "outcomes",
+    help: "Verifications and failed client requests, by outcome result.",
+};
+
+const CACHE_LOOKUPS: Family = Family {
+    name: "cache_lookups",
+    help: "Verifications, by edge cache hit or miss.",
+};
+
+/// DNS requests, by resolver and result: `ok`, or the code of the [`DnsError`].
+const DNS_REQUESTS: Family = Family {
+    name: "dns_requests",
+    help: "DNS requests, by resolver and result.",
+};
+
+const VERIFICATION_DURATION: Family = Family {
+    name: "verification_duration_seconds",
+    help: "How long verifications took, by edge cache hit or miss.",
+};
+
+const DNS_REQUEST_DURATION: Family = Family {
+    name: "dns_request_duration_seconds",
+    help: "How long DNS requests took, by resolver.",
+};
+
+/// The counters and latency histograms of a client request, by family name, then by labels,
+/// e.g. `code="is_crawler",result="yes"`, as logged for downstream aggregation.
+#[derive(Serialize, Default)]
+struct Metrics {
+    counters: BTreeMap<&'static str, BTreeMap<String, u64>>,
+    histograms: BTreeMap<&'static str, BTreeMap<String, Histogram>>,
+    /// The increments of the entries of the [`RATE_COUNTER`] rate counter, which are named
+    /// after the family and the labels rendered by `/metrics`, e.g. `outcomes{result="yes"}`.
+    #[serde(skip)]
+    recent: BTreeMap<String, u32>,
+}
+
+/// The observations of a latency histogram.
+#[derive(Serialize, Default)]
+struct Histogram {
+    /// How many observations fell into each of the [`BUCKETS`], not counting those of lower
+    /// buckets, followed by how many were above all of them.
+    buckets: Vec<u64>,
+    count: u64,
+    /// The sum of the observations, in seconds.
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, seconds: f64) {
+        self.buckets.resize(BUCKETS.len() + 1, 0);
+        let bucket = BUCKETS
+            .iter()
+            .position(|upper_bound| seconds <= *upper_bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+impl Metrics {
+    const fn new() -> Metrics {
+        Metrics {
+            counters: BTreeMap::new(),
+            histograms: BTreeMap::new(),
+            recent: BTreeMap::new(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.counters.is_empty() && self.histograms.is_empty()
+    }
+
+    fn increment(&mut self, family: &Family, labels: String) {
+        *self
+            .counters
+            .entry(family.name)
+            .or_default()
+            .entry(labels)
+            .or_default() += 1;
+    }
+
+    fn increment_recent(&mut self, family: &Family, labels: &str) {
+        *self.recent.entry(recent_entry(family, labels)).or_default() += 1;
+    }
+
+    fn observe(&mut self, family: &Family, labels: String, latency: Duration) {
+        self.histograms
+            .entry(family.name)
+            .or_default()
+            .entry(labels)
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+}
+
+/// The name of the entry of the [`RATE_COUNTER`] rate counter of a series.
+fn recent_entry(family: &Family, labels: &str) -> String {
+    format!("{}{{{}}}", family.name, labels)
+}
+
+/// The metrics of the client request being handled, until [`flush`] writes them out.
+static RECORDED: Mutex<Metrics> = Mutex::new(Metrics::new());
+
+/// Record metrics of the client request being handled, if metrics are on.
+fn record(record: impl FnOnce(&mut Metrics)) {
+    if !config().metrics {
+        return;
+    }
+    if let Ok(mut recorded) = RECORDED.lock() {
+        record(&mut recorded);
+    }
+}
+
+/// Count the outcome of a verification, or of a client request that failed without one.
+pub fn record_outcome(outcome: &Outcome) {
+    record(|metrics| {
+        let result = format!("result=\"{}\"", outcome.result());
+        metrics.increment(&OUTCOMES, format!("code=\"{}\",{}", outcome.code(), result));
+        metrics.increment_recent(&OUTCOMES, &result);
+    });
+}
+
+/// Count a verification as an edge cache `hit` or `miss`, and record how long it took.
+pub fn record_verification(cache: &str, latency: Duration) {
+    record(|metrics| {
+        let labels = format!("cache=\"{}\"", cache);
+        metrics.increment(&CACHE_LOOKUPS, labels.clone());
+        metrics.increment_recent(&CACHE_LOOKUPS, &labels);
+        metrics.observe(&VERIFICATION_DURATION, labels, latency);
+    });
+}
+
+/// Count a DNS request to `resolver` by its result, and record how long it took.
+pub fn record_dns_request(
+    resolver: &str,
+    dns_response: &Result<DnsResponse, DnsError>,
+    latency: Duration,
+) {
+    record(|metrics| {
+        let result = dns_response
+            .as_ref()
+            .map_or_else(|error| error.code(), |_| "ok");
+        let labels = format!("resolver=\"{}\",result=\"{}\"", resolver, result);
+        metrics.increment_recent(&DNS_REQUESTS, &labels);
+        metrics.increment(&DNS_REQUESTS, labels);
+        metrics.observe(
+            &DNS_REQUEST_DURATION,
+            format!("resolver=\"{}\"", resolver),
+            latency,
+        );
+    });
+}
+
+/// Write out the metrics of the client request, once the response has been sent to the client.
+///
+/// The counters and histograms of the request are logged to the log endpoint as a `metrics`
+/// event, whatever the `logging` and `log_sample_rate`, for a downstream system to add up into
+/// Prometheus counters and histograms: every request only ever adds to them, so none are lost.
+/// The counts `/metrics` renders are added to a rate counter.
+///
+/// This is best-effort: a missing log endpoint or rate counter never fails a request.
+pub fn flush() {
+    let recorded = match RECORDED.lock() {
+        Ok(mut recorded) => std::mem::take(&mut *recorded),
+        Err(_) => return,
+    };
+    if recorded.is_empty() {
+        return;
+    }
+
+    if let Ok(mut event) = serde_json::to_value(&recorded) {
+        event["event"] = "metrics".into();
+        logging::log_unsampled(event);
+    }
+    let rate_counter = RateCounter::open(RATE_COUNTER);
+    for (entry, delta) in &recorded.recent {
+        let _ = rate_counter.increment(entry, *delta);
+    }
+}
+
+/// Render how many outcomes, cache lookups and DNS requests the POP serving the request
+/// counted over the last minute, as OpenMetrics gauges.
+///
+/// These are not counters: the totals since the service started are only in the logged
+/// `metrics` events.
+fn render_recent() -> String {
+    let rate_counter = RateCounter::open(RATE_COUNTER);
+    let dns_results = std::iter::once("ok").chain(DnsError::ALL.iter().map(|error| error.code()));
+    let series: [(&Family, Vec<String>); 3] = [
+        (
+            &OUTCOMES,
+            RESULTS
+                .iter()
+                .map(|result| format!("result=\"{}\"", result))
+                .collect(),
+        ),
+        (
+            &CACHE_LOOKUPS,
+            ["hit", "miss"]
+                .iter()
+                .map(|cache| format!("cache=\"{}\"", cache))
+                .collect(),
+        ),
+        (
+            &DNS_REQUESTS,
+            RESOLVERS
+                .iter()
+                .flat_map(|resolver| {
+                    dns_results.clone().map(move |result| {
+                        format!("resolver=\"{}\",result=\"{}\"", resolver.name, result)
+                    })
+                })
+                .collect(),
+        ),
+    ];
+
+    let mut text = String::new();
+    for (family, label_sets) in series {
+        let name = format!("{}_{}_last_minute", PREFIX, family.name);
+        let _ = writeln!(text, "# TYPE {} gauge", name);
+        let _ = writeln!(
+            text,
+            "# HELP {} {} Counted over the last minute, at this POP.",
+            name, family.help
+        );
+        for labels in label_sets {
+            let count = rate_counter
+                .lookup_count(&recent_entry(family, &labels), RECENT)
+                .unwrap_or(0);
+            let _ = writeln!(text, "{}{{{}}} {}", name, labels, count);
+        }
+    }
+    text.push_str("# EOF\n");
+    text
+}
+
+/// Render the recent counts in the OpenMetrics text format, for a client request carrying
+/// the `/metrics` token as a bearer token.
+pub fn handle_metrics_request(req: Request) -> Result<Response, Outcome> {
+    let token = req
+        .get_header(header::AUTHORIZATION)
+        .and_then(|authorization| authorization.to_str().ok())
+        .and_then(|authorization| authorization.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim())
+        .ok_or(Outcome::Unauthorized)?;
+    let metrics_token = metrics_token().ok_or(Outcome::Unauthorized)?;
+    if !constant_time_eq(token.as_bytes(), metrics_token) {
+        return Err(Outcome::Unauthorized);
+    }
+
+    Ok(Response::from_status(StatusCode::OK)
+        .with_header(header::CONTENT_TYPE, OPENMETRICS_CONTENT_TYPE)
+        .with_header(header::CACHE_CONTROL, "no-store")
+        .with_body(render_recent()))
+}
+
+/// The token `/metrics` is protected by, loaded from the Secret Store on first use.
+fn metrics_token() -> Option<&'static [u8]> {
+    static METRICS_TOKEN_BYTES: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+    METRICS_TOKEN_BYTES
+        .get_or_init(|| {
+            let secret = SecretStore::open(SECRET_STORE)
+                .ok()?
+                .try_get(METRICS_TOKEN)
+                .ok()??;
+            let token = secret.plaintext().to_vec();
+            (!token.is_empty()).then_some(token)
+        })
+        .as_deref()
+}
+
+/// Compare two byte strings in a time that only depends on their lengths,
+/// so that a token cannot be guessed byte by byte.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
@@ -0,0 +1,96 @@
+use crate::dns_response::{
+    reverse_lookup_name, DnsAnswer, DnsError, DnsResponse, RecordType, RCODE_NXDOMAIN,
+};
+use crate::verify::Resolve;
+use std::collections::HashMap;
+use std::net::IpAddr;
+
+/// The name of the [`MockResolver`], as reported in its responses.
+pub const MOCK_RESOLVER: &str = "mock";
+
+/// The TTL, in seconds, of the records a [`MockResolver`] answers with.
+pub const MOCK_TTL: u32 = 300;
+
+/// A [`Resolve`] implementation answering from a fixed set of records, to test the verification
+/// logic without a network. Any name it has no answer for does not exist (NXDOMAIN).
+#[derive(Default)]
+pub struct MockResolver {
+    answers: HashMap<(String, RecordType), Result<DnsResponse, DnsError>>,
+}
+
+impl MockResolver {
+    /// A resolver without any records, which answers NXDOMAIN to every query.
+    pub fn new() -> MockResolver {
+        MockResolver::default()
+    }
+
+    /// Answer the reverse lookup of `ip` with `ptr_records`, e.g. `crawl-66-249-66-1.googlebot.com.`.
+    /// No records is an answer without any, rather than NXDOMAIN.
+    pub fn with_ptr(self, ip: IpAddr, ptr_records: &[&str]) -> MockResolver {
+        let name = reverse_lookup_name(ip);
+        let records = ptr_records.iter().map(|ptr_record| ptr_record.to_string());
+        self.with_records(&name, RecordType::Ptr, records)
+    }
+
+    /// Answer the forward lookups of `name` with `addresses`: the IPv4 ones as A records,
+    /// the IPv6 ones as AAAA records.
+    pub fn with_addresses(self, name: &str, addresses: &[IpAddr]) -> MockResolver {
+        let (ipv4, ipv6): (Vec<IpAddr>, Vec<IpAddr>) =
+            addresses.iter().partition(|addr| addr.is_ipv4());
+        self.with_records(name, RecordType::A, ipv4.iter().map(IpAddr::to_string))
+            .with_records(name, RecordType::Aaaa, ipv6.iter().map(IpAddr::to_string))
+    }
+
+    /// Fail the `record_type` query of `name` with `error`.
+    pub fn with_error(
+        mut self,
+        name: &str,
+        record_type: RecordType,
+        error: DnsError,
+    ) -> MockResolver {
+        self.answers
+            .insert((name.to_string(), record_type), Err(error));
+        self
+    }
+
+    /// Answer the `record_type` query of `name` with `records`.
+    fn with_records(
+        mut self,
+        name: &str,
+        record_type: RecordType,
+        records: impl Iterator<Item = String>,
+    ) -> MockResolver {
+        let answer: Vec<DnsAnswer> = records
+            .map(|data| DnsAnswer {
+                record_type: record_type.code(),
+                ttl: MOCK_TTL,
+                data,
+            })
+            .collect();
+        let dns_response = DnsResponse {
+            resolver: None,
+            status: 0,
+            truncated: false,
+            answer,
+        };
+        self.answers
+            .insert((name.to_string(), record_type), Ok(dns_response));
+        self
+    }
+}
+
+impl Resolve for MockResolver {
+    fn resolve(&self, name: &str, record_type: RecordType) -> Result<DnsResponse, DnsError> {
+        let mut dns_response = match self.answers.get(&(name.to_string(), record_type)) {
+            Some(answer) => answer.clone()?,
+            None => DnsResponse {
+                resolver: None,
+                status: RCODE_NXDOMAIN,
+                truncated: false,
+                answer: Vec::new(),
+            },
+        };
+        dns_response.resolver = Some(MOCK_RESOLVER);
+        Ok(dns_response)
+    }
+}
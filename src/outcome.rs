@@ -0,0 +1,759 @@
+use crate::crawlers::{Crawler, AUTO_CRAWLER, CRAWLERS};
+use crate::dns_response::DnsError;
+use http::StatusCode;
+use serde_json::Value;
+
+/// The outcome of a client request: the verdict of a lookup, or why there is none.
+#[derive(Debug, PartialEq)]
+pub enum Outcome {
+    /// The client request matched no route.
+    NotFound,
+    /// The client request had no query string.
+    MissingQueryString,
+    /// The client request had an invalid query string.
+    InvalidQueryString,
+    /// The batch client request, or one of its entries, is invalid.
+    InvalidBatch { reason: String },
+    /// The client request asked to verify a crawler this service does not know.
+    UnknownCrawler { crawler: String },
+    /// The token check client request had no token.
+    MissingToken,
+    /// The client request did not carry valid credentials.
+    Unauthorized,
+    /// The client request's credentials do not grant access to what it asked for.
+    Forbidden,
+    /// The caller sent too many requests, and is refused for `retry_after` seconds.
+    RateLimited { retry_after: u64 },
+    /// Every DNS resolver failed.
+    GoogleDnsFailed,
+    /// The last DNS resolver did not answer in time.
+    DnsTimeout,
+    /// The last DNS resolver returned a response that could not be parsed.
+    InvalidDnsResponse,
+    /// The last DNS resolver failed to resolve a name (SERVFAIL).
+    DnsServerFailure,
+    /// The budget of DNS requests of this service is spent.
+    DnsRateLimited,
+    /// The client IP is within one of the crawler's published IP ranges.
+    InPublishedIpRange {
+        crawler: &'static str,
+        list: &'static str,
+        prefix: String,
+    },
+    /// The client request came from the crawler: `ptr_record`, one of the `ptr_records` of
+    /// the IP, resolves back to it. `ttl` is the lowest TTL of the PTR and forward records
+    /// it was verified with.
+    IsCrawler {
+        crawler: &'static str,
+        ptr_record: String,
+        ptr_records: Vec<String>,
+        ttl: u32,
+    },
+    /// The client request did not come from the requested crawler,
+    /// or from any known crawler if `crawler` is `None`.
+    /// `ptr_record` is the first of the `ptr_records` of the IP.
+    NotCrawler {
+        crawler: Option<&'static str>,
+        ptr_record: String,
+        ptr_records: Vec<String>,
+    },
+    /// `ptr_record`, one of the `ptr_records` of the IP, is a crawler domain, but it does not
+    /// resolve back to the client IP.
+    ForwardLookupMismatch {
+        crawler: &'static str,
+        ptr_record: String,
+        ptr_records: Vec<String>,
+    },
+    /// No PTR Answer was found.
+    NoPtrAnswer,
+    /// No reverse DNS name exists for the IP (NXDOMAIN).
+    PtrNxDomain,
+    /// The IP is within a range of the allow list, so it is verified regardless of DNS.
+    AllowListed { prefix: String },
+    /// The IP is within a range of the deny list, so it is not verified regardless of DNS.
+    DenyListed { prefix: String },
+    /// The client User-Agent claims to be the crawler, but the IP could not be verified as it.
+    SpoofedCrawler {
+        crawler: &'static str,
+        ptr_record: Option<String>,
+        ptr_records: Vec<String>,
+    },
+    /// The origin fronted in inline mode could not be reached.
+    OriginUnavailable,
+    /// The origin fronted in inline mode did not answer in time.
+    OriginTimeout,
+}
+
+impl Outcome {
+    /// The stable, machine-readable `code` of the outcome, also logged.
+    pub fn code(&self) -> &'static str {
+        use Outcome::*;
+        match self {
+            NotFound => "not_found",
+            MissingQueryString => "missing_query_string",
+            InvalidQueryString => "invalid_query_string",
+            InvalidBatch { .. } => "invalid_batch",
+            UnknownCrawler { .. } => "unknown_crawler",
+            MissingToken => "missing_token",
+            Unauthorized => "unauthorized",
+            Forbidden => "forbidden",
+            RateLimited { .. } => "rate_limited",
+            GoogleDnsFailed => "dns_failed",
+            DnsTimeout => "dns_timeout",
+            InvalidDnsResponse => "invalid_dns_response",
+            DnsServerFailure => "dns_server_failure",
+            DnsRateLimited => "dns_rate_limited",
+            InPublishedIpRange { .. } => "in_published_ip_range",
+            IsCrawler { .. } => "is_crawler",
+            NotCrawler { .. } => "not_crawler",
+            ForwardLookupMismatch { .. } => "forward_lookup_mismatch",
+            NoPtrAnswer => "no_ptr_answer",
+            PtrNxDomain => "ptr_nxdomain",
+            AllowListed { .. } => "allow_listed",
+            DenyListed { .. } => "deny_listed",
+            SpoofedCrawler { .. } => "spoofed_crawler",
+            OriginUnavailable => "origin_unavailable",
+            OriginTimeout => "origin_timeout",
+        }
+    }
+
+    /// The `result` of the lookup: `yes`, `no`, `spoofed` or `error`.
+    pub fn result(&self) -> &'static str {
+        use Outcome::*;
+        match self {
+            NotFound
+            | MissingQueryString
+            | InvalidQueryString
+            | InvalidBatch { .. }
+            | UnknownCrawler { .. }
+            | MissingToken
+            | Unauthorized
+            | Forbidden
+            | RateLimited { .. }
+            | GoogleDnsFailed
+            | DnsTimeout
+            | InvalidDnsResponse
+            | DnsServerFailure
+            | DnsRateLimited
+            | OriginUnavailable
+            | OriginTimeout => "error",
+            InPublishedIpRange { .. } | IsCrawler { .. } | AllowListed { .. } => "yes",
+            NotCrawler { .. }
+            | ForwardLookupMismatch { .. }
+            | NoPtrAnswer
+            | PtrNxDomain
+            | DenyListed { .. } => "no",
+            SpoofedCrawler { .. } => "spoofed",
+        }
+    }
+
+    /// Whether the lookup reached a decision about the IP, rather than failing.
+    pub fn is_verdict(&self) -> bool {
+        use Outcome::*;
+        matches!(
+            self,
+            InPublishedIpRange { .. }
+                | IsCrawler { .. }
+                | NotCrawler { .. }
+                | ForwardLookupMismatch { .. }
+                | NoPtrAnswer
+                | PtrNxDomain
+                | AllowListed { .. }
+                | DenyListed { .. }
+                | SpoofedCrawler { .. }
+        )
+    }
+
+    /// The crawler the IP was verified to belong to, if any.
+    pub fn verified_crawler(&self) -> Option<&'static str> {
+        use Outcome::*;
+        match self {
+            InPublishedIpRange { crawler, .. } | IsCrawler { crawler, .. } => Some(crawler),
+            _ => None,
+        }
+    }
+
+    /// Flag the outcome as spoofed if a User-Agent claimed to be `claimed_crawler`,
+    /// but the IP was not verified to belong to it. The override lists are final.
+    pub fn check_claim(self, claimed_crawler: Option<&'static Crawler>) -> Outcome {
+        if matches!(
+            self,
+            Outcome::AllowListed { .. } | Outcome::DenyListed { .. }
+        ) {
+            return self;
+        }
+
+        match claimed_crawler {
+            Some(claimed_crawler)
+                if self.is_verdict() && self.verified_crawler() != Some(claimed_crawler.name) =>
+            {
+                Outcome::SpoofedCrawler {
+                    crawler: claimed_crawler.name,
+                    ptr_record: self.ptr_record().map(str::to_string),
+                    ptr_records: self.ptr_records().to_vec(),
+                }
+            }
+            _ => self,
+        }
+    }
+
+    /// How long, in seconds, the caller should wait before sending the request again,
+    /// if it was refused for now.
+    pub fn retry_after(&self) -> Option<u64> {
+        match self {
+            Outcome::RateLimited { retry_after } => Some(*retry_after),
+            _ => None,
+        }
+    }
+
+    /// The PTR record the decision was based on, if any.
+    pub fn ptr_record(&self) -> Option<&str> {
+        use Outcome::*;
+        match self {
+            IsCrawler { ptr_record, .. }
+            | NotCrawler { ptr_record, .. }
+            | ForwardLookupMismatch { ptr_record, .. } => Some(ptr_record),
+            SpoofedCrawler { ptr_record, .. } => ptr_record.as_deref(),
+            _ => None,
+        }
+    }
+
+    /// Every PTR record of the IP, if they were looked up.
+    pub fn ptr_records(&self) -> &[String] {
+        use Outcome::*;
+        match self {
+            IsCrawler { ptr_records, .. }
+            | NotCrawler { ptr_records, .. }
+            | ForwardLookupMismatch { ptr_records, .. }
+            | SpoofedCrawler { ptr_records, .. } => ptr_records,
+            _ => &[],
+        }
+    }
+
+    /// Whether the PTR record's domain resolved back to the IP, if it was looked up.
+    pub fn forward_confirmed(&self) -> Option<bool> {
+        match self {
+            Outcome::IsCrawler { .. } => Some(true),
+            Outcome::ForwardLookupMismatch { .. } => Some(false),
+            _ => None,
+        }
+    }
+
+    /// The HTTP status and JSON body describing the outcome.
+    pub fn into_status_and_json(self) -> (StatusCode, Value) {
+        use Outcome::*;
+        let result = self.result();
+        let code = self.code();
+        let (reason, status, crawler) = match self {
+            NotFound => (
+                "Either the page you requested could not be found or the HTTP method is not allowed."
+                    .to_string(),
+                StatusCode::NOT_FOUND,
+                None,
+            ),
+            MissingQueryString => (
+                "Missing query string ?ip=a.b.c.d or ?ip=x:x::x".to_string(),
+                StatusCode::BAD_REQUEST,
+                None,
+            ),
+            InvalidQueryString => (
+                "Invalid query string ?ip=a.b.c.d or ?ip=x:x::x".to_string(),
+                StatusCode::BAD_REQUEST,
+                None,
+            ),
+            InvalidBatch { reason } => (reason, StatusCode::BAD_REQUEST, None),
+            UnknownCrawler { crawler } => (
+                format!(
+                    "Unknown crawler {}, expected {} or one of: {}",
+                    crawler,
+                    AUTO_CRAWLER,
+                    CRAWLERS
+                        .iter()
+                        .map(|crawler| crawler.name)
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ),
+                StatusCode::BAD_REQUEST,
+                None,
+            ),
+            MissingToken => (
+                "Missing query string ?token=value".to_string(),
+                StatusCode::BAD_REQUEST,
+                None,
+            ),
+            Unauthorized => (
+                "Missing or invalid credentials".to_string(),
+                StatusCode::UNAUTHORIZED,
+                None,
+            ),
+            Forbidden => (
+                "The credentials do not grant access to this endpoint".to_string(),
+                StatusCode::FORBIDDEN,
+                None,
+            ),
+            RateLimited { retry_after } => (
+                format!("Too many requests, retry in {} seconds", retry_after),
+                StatusCode::TOO_MANY_REQUESTS,
+                None,
+            ),
+            GoogleDnsFailed => (
+                "Every DNS resolver failed".to_string(),
+                StatusCode::BAD_GATEWAY,
+                None,
+            ),
+            DnsTimeout => (
+                "The DNS resolver did not answer in time".to_string(),
+                StatusCode::GATEWAY_TIMEOUT,
+                None,
+            ),
+            InvalidDnsResponse => (
+                "The DNS resolver returned an invalid response".to_string(),
+                StatusCode::BAD_GATEWAY,
+                None,
+            ),
+            DnsServerFailure => (
+                "The DNS resolver could not resolve the name (SERVFAIL)".to_string(),
+                StatusCode::BAD_GATEWAY,
+                None,
+            ),
+            DnsRateLimited => (
+                "The budget of DNS requests is spent, retry later".to_string(),
+                StatusCode::SERVICE_UNAVAILABLE,
+                None,
+            ),
+            InPublishedIpRange {
+                crawler,
+                list,
+                prefix,
+            } => (
+                format!("IP is within the published {} range {}", list, prefix),
+                StatusCode::OK,
+                Some(crawler),
+            ),
+            IsCrawler {
+                crawler,
+                ptr_record,
+                ..
+            } => (
+                format!("Reverse lookup is {}", ptr_record),
+                StatusCode::OK,
+                Some(crawler),
+            ),
+            NotCrawler {
+                crawler: Some(crawler),
+                ptr_record,
+                ..
+            } => (
+                format!("Reverse lookup is {}, not a {} domain.", ptr_record, crawler),
+                StatusCode::OK,
+                None,
+            ),
+            NotCrawler {
+                crawler: None,
+                ptr_record,
+                ..
+            } => (
+                format!(
+                    "Reverse lookup is {}, not a domain of any known crawler.",
+                    ptr_record
+                ),
+                StatusCode::OK,
+                None,
+            ),
+            ForwardLookupMismatch {
+                crawler,
+                ptr_record,
+                ..
+            } => (
+                format!(
+                    "Reverse lookup is {}, but its forward lookup does not resolve back to this IP.",
+                    ptr_record
+                ),
+                StatusCode::OK,
+                Some(crawler),
+            ),
+            NoPtrAnswer => (
+                "No PTR Answer for this reverse lookup.".to_string(),
+                StatusCode::OK,
+                None,
+            ),
+            PtrNxDomain => (
+                "No reverse DNS name exists for this IP (NXDOMAIN).".to_string(),
+                StatusCode::OK,
+                None,
+            ),
+            AllowListed { prefix } => (
+                format!("IP is within the allow list range {}", prefix),
+                StatusCode::OK,
+                None,
+            ),
+            DenyListed { prefix } => (
+                format!("IP is within the deny list range {}", prefix),
+                StatusCode::OK,
+                None,
+            ),
+            SpoofedCrawler {
+                crawler,
+                ptr_record,
+                ..
+            } => (
+                match ptr_record {
+                    Some(ptr_record) => format!(
+                        "User-Agent claims to be {}, but reverse lookup is {}.",
+                        crawler, ptr_record
+                    ),
+                    None => format!(
+                        "User-Agent claims to be {}, but the IP does not belong to it.",
+                        crawler
+                    ),
+                },
+                StatusCode::OK,
+                Some(crawler),
+            ),
+            OriginUnavailable => (
+                "The origin could not be reached".to_string(),
+                StatusCode::BAD_GATEWAY,
+                None,
+            ),
+            OriginTimeout => (
+                "The origin did not answer in time".to_string(),
+                StatusCode::GATEWAY_TIMEOUT,
+                None,
+            ),
+        };
+        let mut body_json = serde_json::json!({
+            "result": result,
+            "code": code,
+            "reason": reason,
+        });
+        if let Some(crawler) = crawler {
+            body_json["crawler"] = crawler.into();
+        }
+
+        (status, body_json)
+    }
+}
+
+/// Convert a failed DNS query into the [`Outcome`] of the lookup request it was made for.
+impl From<DnsError> for Outcome {
+    fn from(error: DnsError) -> Self {
+        match error {
+            DnsError::InvalidResponse | DnsError::InvalidName => Outcome::InvalidDnsResponse,
+            DnsError::ServerFailure => Outcome::DnsServerFailure,
+            DnsError::Timeout => Outcome::DnsTimeout,
+            DnsError::RateLimited => Outcome::DnsRateLimited,
+            DnsError::Unavailable
+            | DnsError::Rejected
+            | DnsError::Truncated
+            | DnsError::ErrorStatus => Outcome::GoogleDnsFailed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn googlebot() -> &'static Crawler {
+        Crawler::find("googlebot").unwrap()
+    }
+
+    fn is_crawler() -> Outcome {
+        Outcome::IsCrawler {
+            crawler: "googlebot",
+            ptr_record: "crawl-66-249-66-1.googlebot.com.".to_string(),
+            ptr_records: vec!["crawl-66-249-66-1.googlebot.com.".to_string()],
+            ttl: 300,
+        }
+    }
+
+    fn not_crawler() -> Outcome {
+        Outcome::NotCrawler {
+            crawler: Some("googlebot"),
+            ptr_record: "host.example.com.".to_string(),
+            ptr_records: vec!["host.example.com.".to_string()],
+        }
+    }
+
+    /// Every outcome, with the status, `code` and `result` it is reported with.
+    fn every_outcome() -> Vec<(Outcome, StatusCode, &'static str, &'static str)> {
+        use Outcome::*;
+        vec![
+            (NotFound, StatusCode::NOT_FOUND, "not_found", "error"),
+            (
+                MissingQueryString,
+                StatusCode::BAD_REQUEST,
+                "missing_query_string",
+                "error",
+            ),
+            (
+                InvalidQueryString,
+                StatusCode::BAD_REQUEST,
+                "invalid_query_string",
+                "error",
+            ),
+            (
+                InvalidBatch {
+                    reason: "Invalid batch".to_string(),
+                },
+                StatusCode::BAD_REQUEST,
+                "invalid_batch",
+                "error",
+            ),
+            (
+                UnknownCrawler {
+                    crawler: "examplebot".to_string(),
+                },
+                StatusCode::BAD_REQUEST,
+                "unknown_crawler",
+                "error",
+            ),
+            (
+                MissingToken,
+                StatusCode::BAD_REQUEST,
+                "missing_token",
+                "error",
+            ),
+            (
+                Unauthorized,
+                StatusCode::UNAUTHORIZED,
+                "unauthorized",
+                "error",
+            ),
+            (Forbidden, StatusCode::FORBIDDEN, "forbidden", "error"),
+            (
+                RateLimited { retry_after: 60 },
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limited",
+                "error",
+            ),
+            (
+                GoogleDnsFailed,
+                StatusCode::BAD_GATEWAY,
+                "dns_failed",
+                "error",
+            ),
+            (
+                DnsTimeout,
+                StatusCode::GATEWAY_TIMEOUT,
+                "dns_timeout",
+                "error",
+            ),
+            (
+                InvalidDnsResponse,
+                StatusCode::BAD_GATEWAY,
+                "invalid_dns_response",
+                "error",
+            ),
+            (
+                DnsServerFailure,
+                StatusCode::BAD_GATEWAY,
+                "dns_server_failure",
+                "error",
+            ),
+            (
+                DnsRateLimited,
+                StatusCode::SERVICE_UNAVAILABLE,
+                "dns_rate_limited",
+                "error",
+            ),
+            (
+                InPublishedIpRange {
+                    crawler: "googlebot",
+                    list: "googlebot",
+                    prefix: "66.249.64.0/27".to_string(),
+                },
+                StatusCode::OK,
+                "in_published_ip_range",
+                "yes",
+            ),
+            (is_crawler(), StatusCode::OK, "is_crawler", "yes"),
+            (not_crawler(), StatusCode::OK, "not_crawler", "no"),
+            (
+                NotCrawler {
+                    crawler: None,
+                    ptr_record: "host.example.com.".to_string(),
+                    ptr_records: vec!["host.example.com.".to_string()],
+                },
+                StatusCode::OK,
+                "not_crawler",
+                "no",
+            ),
+            (
+                ForwardLookupMismatch {
+                    crawler: "googlebot",
+                    ptr_record: "crawl-66-249-66-1.googlebot.com.".to_string(),
+                    ptr_records: vec!["crawl-66-249-66-1.googlebot.com.".to_string()],
+                },
+                StatusCode::OK,
+                "forward_lookup_mismatch",
+                "no",
+            ),
+            (NoPtrAnswer, StatusCode::OK, "no_ptr_answer", "no"),
+            (PtrNxDomain, StatusCode::OK, "ptr_nxdomain", "no"),
+            (
+                AllowListed {
+                    prefix: "192.0.2.0/24".to_string(),
+                },
+                StatusCode::OK,
+                "allow_listed",
+                "yes",
+            ),
+            (
+                DenyListed {
+                    prefix: "192.0.2.0/24".to_string(),
+                },
+                StatusCode::OK,
+                "deny_listed",
+                "no",
+            ),
+            (
+                SpoofedCrawler {
+                    crawler: "googlebot",
+                    ptr_record: Some("host.example.com.".to_string()),
+                    ptr_records: vec!["host.example.com.".to_string()],
+                },
+                StatusCode::OK,
+                "spoofed_crawler",
+                "spoofed",
+            ),
+            (
+                SpoofedCrawler {
+                    crawler: "googlebot",
+                    ptr_record: None,
+                    ptr_records: vec![],
+                },
+                StatusCode::OK,
+                "spoofed_crawler",
+                "spoofed",
+            ),
+            (
+                OriginUnavailable,
+                StatusCode::BAD_GATEWAY,
+                "origin_unavailable",
+                "error",
+            ),
+            (
+                OriginTimeout,
+                StatusCode::GATEWAY_TIMEOUT,
+                "origin_timeout",
+                "error",
+            ),
+        ]
+    }
+
+    #[test]
+    fn every_outcome_has_a_status_code_and_result() {
+        for (outcome, status, code, result) in every_outcome() {
+            assert_eq!(outcome.code(), code);
+            assert_eq!(outcome.result(), result, "{}", code);
+            assert_eq!(outcome.is_verdict(), result != "error", "{}", code);
+
+            let (actual_status, body_json) = outcome.into_status_and_json();
+            assert_eq!(actual_status, status, "{}", code);
+            assert_eq!(body_json["code"], code);
+            assert_eq!(body_json["result"], result, "{}", code);
+            assert!(body_json["reason"].is_string(), "{}", code);
+        }
+    }
+
+    #[test]
+    fn verified_outcomes_name_the_crawler() {
+        assert_eq!(is_crawler().verified_crawler(), Some("googlebot"));
+        assert_eq!(
+            is_crawler().into_status_and_json().1["crawler"],
+            "googlebot"
+        );
+        assert_eq!(not_crawler().verified_crawler(), None);
+        assert!(not_crawler()
+            .into_status_and_json()
+            .1
+            .get("crawler")
+            .is_none());
+    }
+
+    #[test]
+    fn only_rate_limited_requests_are_retried_later() {
+        assert_eq!(
+            Outcome::RateLimited { retry_after: 60 }.retry_after(),
+            Some(60)
+        );
+        assert_eq!(Outcome::DnsTimeout.retry_after(), None);
+    }
+
+    #[test]
+    fn forward_confirmation() {
+        assert_eq!(is_crawler().forward_confirmed(), Some(true));
+        assert_eq!(not_crawler().forward_confirmed(), None);
+        let mismatch = Outcome::ForwardLookupMismatch {
+            crawler: "googlebot",
+            ptr_record: "crawl-66-249-66-1.googlebot.com.".to_string(),
+            ptr_records: vec!["crawl-66-249-66-1.googlebot.com.".to_string()],
+        };
+        assert_eq!(mismatch.forward_confirmed(), Some(false));
+    }
+
+    #[test]
+    fn a_verified_claim_stands() {
+        assert_eq!(is_crawler().check_claim(Some(googlebot())), is_crawler());
+        assert_eq!(is_crawler().check_claim(None), is_crawler());
+    }
+
+    #[test]
+    fn an_unverified_claim_is_spoofed() {
+        assert_eq!(
+            not_crawler().check_claim(Some(googlebot())),
+            Outcome::SpoofedCrawler {
+                crawler: "googlebot",
+                ptr_record: Some("host.example.com.".to_string()),
+                ptr_records: vec!["host.example.com.".to_string()],
+            }
+        );
+        assert_eq!(
+            Outcome::PtrNxDomain.check_claim(Some(googlebot())),
+            Outcome::SpoofedCrawler {
+                crawler: "googlebot",
+                ptr_record: None,
+                ptr_records: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn a_claim_of_another_crawler_is_spoofed() {
+        let bingbot = Crawler::find("bingbot").unwrap();
+        assert_eq!(
+            is_crawler().check_claim(Some(bingbot)).code(),
+            "spoofed_crawler"
+        );
+    }
+
+    #[test]
+    fn errors_and_override_lists_are_not_spoofed() {
+        for outcome in [
+            Outcome::DnsTimeout,
+            Outcome::AllowListed {
+                prefix: "192.0.2.0/24".to_string(),
+            },
+            Outcome::DenyListed {
+                prefix: "192.0.2.0/24".to_string(),
+            },
+        ] {
+            let code = outcome.code();
+            assert_eq!(outcome.check_claim(Some(googlebot())).code(), code);
+        }
+    }
+
+    #[test]
+    fn unknown_crawler_lists_every_crawler() {
+        let (_, body_json) = Outcome::UnknownCrawler {
+            crawler: "examplebot".to_string(),
+        }
+        .into_status_and_json();
+        let reason = body_json["reason"].as_str().unwrap();
+        assert!(reason.contains(AUTO_CRAWLER));
+        for crawler in CRAWLERS.iter() {
+            assert!(reason.contains(crawler.name), "{}", crawler.name);
+        }
+    }
+}
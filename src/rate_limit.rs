@@ -0,0 +1,80 @@
+use crate::auth::ApiKey;
+use crate::client_ip::client_ip;
+use crate::config::config;
+use crawler_verification::outcome::Outcome;
+use fastly::erl::{Penaltybox, RateCounter, RateWindow, ERL};
+use fastly::Request;
+
+/// The name of the rate counter of lookup requests, by caller.
+const CALLER_RATE_COUNTER: &str = "crawler_verification_callers";
+
+/// The name of the penalty box of the callers over their rate limit.
+const PENALTY_BOX: &str = "crawler_verification_penalty_box";
+
+/// The name of the rate counter of the DNS requests this service sends.
+const DNS_RATE_COUNTER: &str = "crawler_verification_dns";
+
+/// The entry of the [`DNS_RATE_COUNTER`] rate counter, which counts every DNS request.
+const DNS_ENTRY: &str = "dns";
+
+/// The window every rate is averaged over.
+const RATE_WINDOW: RateWindow = RateWindow::TenSecs;
+
+/// Refuse a lookup request if its client IP is over its rate limit, or in the penalty box for
+/// having been over it. The request counts as `lookups` lookups: one for a single lookup, and
+/// one per distinct IP for a batch.
+///
+/// This is checked before the request is authenticated, so that callers guessing API keys
+/// are limited too, and never cost more than their share of KV Store lookups.
+/// Rate limiting fails open: if the rate counter cannot be checked, the request goes through.
+pub fn check_ip(req: &Request, lookups: u32) -> Result<(), Outcome> {
+    match (config().rate_limit_ip, client_ip(req)) {
+        (Some(limit), Some((ip, _))) => check_rate(&format!("ip:{}", ip), lookups, limit),
+        _ => Ok(()),
+    }
+}
+
+/// Like [`check_ip`], but for the API key the request was authenticated with, if any,
+/// once it has been.
+pub fn check_api_key(api_key: Option<&ApiKey>, lookups: u32) -> Result<(), Outcome> {
+    let api_key = match api_key {
+        Some(api_key) => api_key,
+        None => return Ok(()),
+    };
+    match api_key.rate_limit.or(config().rate_limit_api_key) {
+        Some(limit) => check_rate(&format!("key:{}", api_key.id), lookups, limit),
+        None => Ok(()),
+    }
+}
+
+/// Count `lookups` lookups of the caller `entry`, and refuse them if the caller is over `limit`.
+fn check_rate(entry: &str, lookups: u32, limit: u32) -> Result<(), Outcome> {
+    let penalty = config().rate_limit_penalty;
+    let erl = ERL::open(
+        RateCounter::open(CALLER_RATE_COUNTER),
+        Penaltybox::open(PENALTY_BOX),
+    );
+    match erl.check_rate(entry, lookups, RATE_WINDOW, limit, penalty) {
+        Ok(true) => Err(Outcome::RateLimited {
+            retry_after: penalty.as_secs(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the DNS requests this service sent are over the configured `dns_rate_limit`,
+/// in which case no more are sent until the rate drops.
+pub fn dns_budget_spent() -> bool {
+    config().dns_rate_limit.is_some_and(|limit| {
+        RateCounter::open(DNS_RATE_COUNTER)
+            .lookup_rate(DNS_ENTRY, RATE_WINDOW)
+            .is_ok_and(|rate| rate >= limit)
+    })
+}
+
+/// Count a DNS request against the `dns_rate_limit`, if there is one.
+pub fn count_dns_request() {
+    if config().dns_rate_limit.is_some() {
+        let _ = RateCounter::open(DNS_RATE_COUNTER).increment(DNS_ENTRY, 1);
+    }
+}
@@ -0,0 +1,207 @@
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::net::IpAddr;
+
+/// The claims of a verification token.
+///
+/// A token is `<payload>.<signature>`, where `payload` is these claims as base64url-encoded
+/// JSON, e.g. `{"ip":"66.249.66.1","crawler":"googlebot","result":"yes","exp":1700000000}`,
+/// and `signature` is the base64url-encoded HMAC-SHA256 of `payload` with the signing key,
+/// so that an origin holding the key can validate tokens offline.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Claims {
+    /// The verified IP.
+    pub ip: String,
+    /// The crawler the IP was verified against, if any.
+    pub crawler: Option<String>,
+    /// The `result` of the verification: `yes`, `no` or `spoofed`.
+    pub result: String,
+    /// When the token expires, in seconds since the Unix epoch.
+    pub exp: u64,
+}
+
+/// Why a verification token is not valid.
+#[derive(Debug, PartialEq)]
+pub enum TokenError {
+    /// The token is not a payload and a signature, or its payload is not valid claims.
+    Malformed,
+    /// The signature does not match the payload.
+    BadSignature,
+    /// The token has expired.
+    Expired,
+    /// The token was issued for another IP than the expected one.
+    OtherIp,
+    /// There is no signing key to check the token with.
+    NoSigningKey,
+}
+
+impl TokenError {
+    /// A description of the error, as reported by the token check endpoint.
+    pub fn reason(&self) -> &'static str {
+        match self {
+            TokenError::Malformed => "The token is malformed",
+            TokenError::BadSignature => "The token signature is invalid",
+            TokenError::Expired => "The token has expired",
+            TokenError::OtherIp => "The token was issued for another IP",
+            TokenError::NoSigningKey => "No signing key is configured",
+        }
+    }
+}
+
+/// Sign `claims` with `key` into a verification token.
+pub fn sign(claims: &Claims, key: &[u8]) -> Option<String> {
+    let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims).ok()?);
+    let signature = URL_SAFE_NO_PAD.encode(mac(key, &payload).finalize().into_bytes());
+    Some(format!("{}.{}", payload, signature))
+}
+
+/// Check the signature of a verification token with `key`, and that it has not expired
+/// at `now`, in seconds since the Unix epoch, returning its claims.
+/// With an `expected_ip`, also check that the token was issued for that IP.
+pub fn check(
+    token: &str,
+    key: &[u8],
+    now: u64,
+    expected_ip: Option<IpAddr>,
+) -> Result<Claims, TokenError> {
+    let (payload, signature) = token.trim().split_once('.').ok_or(TokenError::Malformed)?;
+    let signature = URL_SAFE_NO_PAD
+        .decode(signature)
+        .map_err(|_| TokenError::Malformed)?;
+    mac(key, payload)
+        .verify_slice(&signature)
+        .map_err(|_| TokenError::BadSignature)?;
+
+    let claims: Claims = URL_SAFE_NO_PAD
+        .decode(payload)
+        .ok()
+        .and_then(|payload| serde_json::from_slice(&payload).ok())
+        .ok_or(TokenError::Malformed)?;
+    if claims.exp <= now {
+        return Err(TokenError::Expired);
+    }
+    if expected_ip.is_some_and(|ip| claims.ip != ip.to_string()) {
+        return Err(TokenError::OtherIp);
+    }
+    Ok(claims)
+}
+
+/// The HMAC-SHA256 of `payload` with `key`, ready to be finalized or verified.
+fn mac(key: &[u8], payload: &str) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(payload.as_bytes());
+    mac
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &[u8] = b"test-signing-key";
+    const NOW: u64 = 1_700_000_000;
+
+    fn claims() -> Claims {
+        Claims {
+            ip: "66.249.66.1".to_string(),
+            crawler: Some("googlebot".to_string()),
+            result: "yes".to_string(),
+            exp: NOW + 300,
+        }
+    }
+
+    fn token() -> String {
+        sign(&claims(), KEY).unwrap()
+    }
+
+    #[test]
+    fn round_trip() {
+        assert_eq!(check(&token(), KEY, NOW, None), Ok(claims()));
+        assert_eq!(
+            check(&format!(" {}\n", token()), KEY, NOW, None),
+            Ok(claims())
+        );
+
+        let claims = Claims {
+            crawler: None,
+            result: "no".to_string(),
+            ..claims()
+        };
+        let token = sign(&claims, KEY).unwrap();
+        assert_eq!(check(&token, KEY, NOW, None), Ok(claims));
+    }
+
+    #[test]
+    fn rejects_a_tampered_payload() {
+        let token = token();
+        let (_, signature) = token.split_once('.').unwrap();
+        let claims = Claims {
+            ip: "203.0.113.7".to_string(),
+            ..claims()
+        };
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims).unwrap());
+        assert_eq!(
+            check(&format!("{}.{}", payload, signature), KEY, NOW, None),
+            Err(TokenError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_a_tampered_signature() {
+        let token = token();
+        let (payload, _) = token.split_once('.').unwrap();
+        let other_signature = URL_SAFE_NO_PAD.encode([0u8; 32]);
+        assert_eq!(
+            check(&format!("{}.{}", payload, other_signature), KEY, NOW, None),
+            Err(TokenError::BadSignature)
+        );
+        assert_eq!(
+            check(&format!("{}.", payload), KEY, NOW, None),
+            Err(TokenError::BadSignature)
+        );
+        assert_eq!(
+            check(&token, b"another-key", NOW, None),
+            Err(TokenError::BadSignature)
+        );
+    }
+
+    #[test]
+    fn rejects_an_expired_token() {
+        let exp = claims().exp;
+        assert!(check(&token(), KEY, exp - 1, None).is_ok());
+        assert_eq!(check(&token(), KEY, exp, None), Err(TokenError::Expired));
+    }
+
+    #[test]
+    fn rejects_a_malformed_token() {
+        for token in ["", "no-signature", "payload.not base64!"] {
+            assert_eq!(
+                check(token, KEY, NOW, None),
+                Err(TokenError::Malformed),
+                "{:?}",
+                token
+            );
+        }
+
+        // A signed payload that is not claims.
+        let payload = URL_SAFE_NO_PAD.encode(b"{\"ip\":1}");
+        let signature = URL_SAFE_NO_PAD.encode(mac(KEY, &payload).finalize().into_bytes());
+        assert_eq!(
+            check(&format!("{}.{}", payload, signature), KEY, NOW, None),
+            Err(TokenError::Malformed)
+        );
+    }
+
+    #[test]
+    fn checks_the_ip_the_token_was_issued_for() {
+        let ip = "66.249.66.1".parse().unwrap();
+        assert_eq!(check(&token(), KEY, NOW, Some(ip)), Ok(claims()));
+        let other_ip = "66.249.66.2".parse().unwrap();
+        assert_eq!(
+            check(&token(), KEY, NOW, Some(other_ip)),
+            Err(TokenError::OtherIp)
+        );
+    }
+}
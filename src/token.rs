@@ -0,0 +1,51 @@
+use crate::cache;
+use crate::config::config;
+use crawler_verification::signed_token::{self, Claims, TokenError};
+use fastly::SecretStore;
+use std::net::IpAddr;
+use std::sync::OnceLock;
+
+/// The name of the Secret Store holding the key verification tokens are signed with.
+const SECRET_STORE: &str = "crawler_verification";
+
+/// The name of the signing key in the [`SECRET_STORE`] Secret Store.
+const SIGNING_KEY: &str = "token_key";
+
+/// Issue a verification token for the `result` of verifying `ip` against `crawler`,
+/// valid for the configured `token_ttl`.
+///
+/// Returns `None` if tokens are off, or there is no signing key.
+pub fn issue(ip: IpAddr, crawler: Option<&str>, result: &str) -> Option<String> {
+    if !config().tokens {
+        return None;
+    }
+    let claims = Claims {
+        ip: ip.to_string(),
+        crawler: crawler.map(str::to_string),
+        result: result.to_string(),
+        exp: cache::now() + u64::from(config().token_ttl),
+    };
+    signed_token::sign(&claims, signing_key()?)
+}
+
+/// Check the signature and expiry of a verification token with the signing key, and, with
+/// an `expected_ip`, whether it was issued for that IP, returning its claims.
+pub fn check(token: &str, expected_ip: Option<IpAddr>) -> Result<Claims, TokenError> {
+    let key = signing_key().ok_or(TokenError::NoSigningKey)?;
+    signed_token::check(token, key, cache::now(), expected_ip)
+}
+
+/// The key tokens are signed with, loaded from the Secret Store on first use.
+fn signing_key() -> Option<&'static [u8]> {
+    static SIGNING_KEY_BYTES: OnceLock<Option<Vec<u8>>> = OnceLock::new();
+    SIGNING_KEY_BYTES
+        .get_or_init(|| {
+            let secret = SecretStore::open(SECRET_STORE)
+                .ok()?
+                .try_get(SIGNING_KEY)
+                .ok()??;
+            let key = secret.plaintext().to_vec();
+            (!key.is_empty()).then_some(key)
+        })
+        .as_deref()
+}
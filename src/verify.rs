@@ -0,0 +1,601 @@
+use crate::crawlers::{Crawler, PtrSuffixes};
+use crate::dns_response::{reverse_lookup_name, DnsError, DnsResponse, RecordType};
+use crate::ip_ranges::IpRanges;
+use crate::outcome::Outcome;
+use std::collections::VecDeque;
+use std::net::IpAddr;
+
+/// Something that answers DNS queries: the configured DNS-over-HTTPS resolvers at the edge,
+/// or a mock resolver answering from fixed records in tests.
+pub trait Resolve {
+    /// Query for the `record_type` records of `name`.
+    fn resolve(&self, name: &str, record_type: RecordType) -> Result<DnsResponse, DnsError>;
+}
+
+/// The outcome for `ip` if it is within one of the published `ip_ranges` of the candidates.
+///
+/// Published IP ranges answer the common case without a DNS round-trip. If they cannot be
+/// fetched, `ip_ranges` is empty and the IP is verified by DNS instead.
+pub fn in_published_ip_range(ip_ranges: &IpRanges, ip: IpAddr) -> Option<Outcome> {
+    let ip_range = ip_ranges.lookup(ip)?;
+    Some(Outcome::InPublishedIpRange {
+        crawler: ip_range.crawler.name,
+        list: ip_range.list,
+        prefix: ip_range.prefix,
+    })
+}
+
+/// Verify whether `ip` belongs to one of the `candidates` crawlers: by their published
+/// `ip_ranges` if it is within one, or else by DNS, see [`verify_by_dns`].
+pub fn verify_ip(
+    resolver: &impl Resolve,
+    ip_ranges: &IpRanges,
+    ip: IpAddr,
+    candidates: &[&'static Crawler],
+    auto_identify: bool,
+    ptr_suffixes: &PtrSuffixes,
+) -> (Outcome, Option<&'static str>) {
+    match in_published_ip_range(ip_ranges, ip) {
+        Some(outcome) => (outcome, None),
+        None => verify_by_dns(resolver, ip, candidates, auto_identify, ptr_suffixes),
+    }
+}
+
+/// Verify by DNS whether `ip` belongs to one of the `candidates` crawlers: its PTR record must
+/// be a candidate crawler's domain, which must resolve back to it.
+///
+/// If `auto_identify` is set, the candidates are every known crawler and a negative
+/// outcome does not name one. Also returns the resolver of the last DNS answer, if any.
+pub fn verify_by_dns(
+    resolver: &impl Resolve,
+    ip: IpAddr,
+    candidates: &[&'static Crawler],
+    auto_identify: bool,
+    ptr_suffixes: &PtrSuffixes,
+) -> (Outcome, Option<&'static str>) {
+    let dns_response = match resolver.resolve(&reverse_lookup_name(ip), RecordType::Ptr) {
+        Ok(dns_response) => dns_response,
+        Err(error) => return (error.into(), None),
+    };
+
+    let mut last_resolver = dns_response.resolver;
+    let mut decision = decide_ptr(&dns_response, candidates, auto_identify, ptr_suffixes);
+    loop {
+        let forward_lookup = match decision {
+            PtrDecision::Decided(outcome) => return (outcome, last_resolver),
+            PtrDecision::NeedsForwardLookup(forward_lookup) => forward_lookup,
+        };
+        let dns_response =
+            match resolver.resolve(&forward_lookup.ptr_record, forward_record_type(ip)) {
+                Ok(dns_response) => dns_response,
+                Err(error) => return (error.into(), last_resolver),
+            };
+        last_resolver = dns_response.resolver;
+        decision = forward_lookup.decide(&dns_response, ip);
+    }
+}
+
+/// What the PTR answer of an IP tells about it.
+pub enum PtrDecision {
+    /// The PTR answer is enough to decide.
+    Decided(Outcome),
+    /// A PTR record is a candidate crawler's domain, which must be forward-confirmed.
+    NeedsForwardLookup(ForwardLookup),
+}
+
+/// Decide what the PTR answer of an IP tells about it.
+///
+/// Every PTR record in the answer is considered: those that are a candidate crawler's domain
+/// are forward-confirmed in turn, until one is. A PTR record that is not a host name is never
+/// a crawler's domain.
+pub fn decide_ptr(
+    dns_response: &DnsResponse,
+    candidates: &[&'static Crawler],
+    auto_identify: bool,
+    ptr_suffixes: &PtrSuffixes,
+) -> PtrDecision {
+    if dns_response.is_nxdomain() {
+        return PtrDecision::Decided(Outcome::PtrNxDomain);
+    }
+
+    let ptr_records: Vec<String> = dns_response
+        .answers(RecordType::Ptr)
+        .map(str::to_string)
+        .collect();
+    let first_ptr_record = match ptr_records.first() {
+        Some(ptr_record) => ptr_record.clone(),
+        None => return PtrDecision::Decided(Outcome::NoPtrAnswer),
+    };
+
+    let mut crawler_ptr_records: VecDeque<(&'static Crawler, String)> = ptr_records
+        .iter()
+        .filter(|ptr_record| is_host_name(ptr_record))
+        .filter_map(|ptr_record| {
+            candidates
+                .iter()
+                .find(|crawler| crawler.matches_ptr(ptr_record, ptr_suffixes))
+                .map(|crawler| (*crawler, ptr_record.clone()))
+        })
+        .collect();
+    match crawler_ptr_records.pop_front() {
+        Some((crawler, ptr_record)) => PtrDecision::NeedsForwardLookup(ForwardLookup {
+            crawler,
+            ptr_record,
+            ptr_records,
+            ptr_ttl: dns_response.min_ttl().unwrap_or(0),
+            next: crawler_ptr_records,
+        }),
+        None => PtrDecision::Decided(Outcome::NotCrawler {
+            crawler: (!auto_identify).then(|| candidates[0].name),
+            ptr_record: first_ptr_record,
+            ptr_records,
+        }),
+    }
+}
+
+/// Whether `name` is a host name: dot-separated labels of ASCII letters, digits, `-` and `_`.
+///
+/// PTR records are controlled by whoever holds the reverse zone of an IP. Any other character
+/// could change the meaning of the forward lookup, e.g. `attacker.example#.googlebot.com.`
+/// ends with a crawler's domain, but a `#` would cut the query short to `attacker.example`.
+fn is_host_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.'))
+}
+
+/// A PTR record of a crawler's domain, to be forward-confirmed: the domain must resolve
+/// back to the IP, otherwise anyone controlling reverse DNS for their block could claim it.
+pub struct ForwardLookup {
+    /// The crawler whose domain the PTR record is.
+    pub crawler: &'static Crawler,
+    /// The PTR record to forward-confirm.
+    pub ptr_record: String,
+    /// Every PTR record of the IP, including `ptr_record`.
+    pub ptr_records: Vec<String>,
+    /// The lowest TTL of the PTR answer.
+    pub ptr_ttl: u32,
+    /// The other PTR records that are a candidate crawler's domain, to forward-confirm in turn
+    /// if `ptr_record` is not.
+    next: VecDeque<(&'static Crawler, String)>,
+}
+
+impl ForwardLookup {
+    /// Decide from the A (for IPv4) or AAAA (for IPv6) answer of the PTR record's domain
+    /// whether it points back to `ip`. If it does not, the next PTR record of a candidate
+    /// crawler's domain, if any, is the one to forward-confirm.
+    pub fn decide(mut self, dns_response: &DnsResponse, ip: IpAddr) -> PtrDecision {
+        let forward_confirmed = dns_response
+            .answers(forward_record_type(ip))
+            .filter_map(|data| data.parse::<IpAddr>().ok())
+            .any(|addr| addr == ip);
+
+        if forward_confirmed {
+            return PtrDecision::Decided(Outcome::IsCrawler {
+                crawler: self.crawler.name,
+                ptr_record: self.ptr_record,
+                ptr_records: self.ptr_records,
+                ttl: dns_response.min_ttl().unwrap_or(0).min(self.ptr_ttl),
+            });
+        }
+        match self.next.pop_front() {
+            Some((crawler, ptr_record)) => PtrDecision::NeedsForwardLookup(ForwardLookup {
+                crawler,
+                ptr_record,
+                ..self
+            }),
+            None => PtrDecision::Decided(Outcome::ForwardLookupMismatch {
+                crawler: self.crawler.name,
+                ptr_record: self.ptr_record,
+                ptr_records: self.ptr_records,
+            }),
+        }
+    }
+}
+
+/// The DNS record type that forward-confirms a PTR record of `ip`.
+pub fn forward_record_type(ip: IpAddr) -> RecordType {
+    match ip {
+        IpAddr::V4(_) => RecordType::A,
+        IpAddr::V6(_) => RecordType::Aaaa,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::crawlers::CRAWLERS;
+    use crate::mock::{MockResolver, MOCK_RESOLVER, MOCK_TTL};
+
+    const GOOGLEBOT_IP: &str = "66.249.66.1";
+    const GOOGLEBOT_PTR: &str = "crawl-66-249-66-1.googlebot.com.";
+
+    fn ip(ip: &str) -> IpAddr {
+        ip.parse().unwrap()
+    }
+
+    fn googlebot() -> &'static Crawler {
+        Crawler::find("googlebot").unwrap()
+    }
+
+    fn every_crawler() -> Vec<&'static Crawler> {
+        CRAWLERS.iter().collect()
+    }
+
+    /// A resolver for which `GOOGLEBOT_IP` is a forward-confirmed Googlebot.
+    fn googlebot_resolver() -> MockResolver {
+        MockResolver::new()
+            .with_ptr(ip(GOOGLEBOT_IP), &[GOOGLEBOT_PTR])
+            .with_addresses(GOOGLEBOT_PTR, &[ip(GOOGLEBOT_IP)])
+    }
+
+    fn verify(resolver: &MockResolver, ip: IpAddr, candidates: &[&'static Crawler]) -> Outcome {
+        verify_by_dns(resolver, ip, candidates, false, &PtrSuffixes::new()).0
+    }
+
+    #[test]
+    fn is_crawler_when_the_ptr_record_resolves_back() {
+        let (outcome, resolver) = verify_by_dns(
+            &googlebot_resolver(),
+            ip(GOOGLEBOT_IP),
+            &[googlebot()],
+            false,
+            &PtrSuffixes::new(),
+        );
+        assert_eq!(
+            outcome,
+            Outcome::IsCrawler {
+                crawler: "googlebot",
+                ptr_record: GOOGLEBOT_PTR.to_string(),
+                ptr_records: vec![GOOGLEBOT_PTR.to_string()],
+                ttl: MOCK_TTL,
+            }
+        );
+        assert_eq!(resolver, Some(MOCK_RESOLVER));
+    }
+
+    #[test]
+    fn is_crawler_over_ipv6() {
+        let ipv6 = ip("2001:4860:4801:10::1");
+        let ptr_record = "crawl-2001-4860-4801-10--1.googlebot.com.";
+        let resolver = MockResolver::new()
+            .with_ptr(ipv6, &[ptr_record])
+            .with_addresses(ptr_record, &[ipv6]);
+        assert_eq!(
+            verify(&resolver, ipv6, &[googlebot()]),
+            Outcome::IsCrawler {
+                crawler: "googlebot",
+                ptr_record: ptr_record.to_string(),
+                ptr_records: vec![ptr_record.to_string()],
+                ttl: MOCK_TTL,
+            }
+        );
+    }
+
+    #[test]
+    fn auto_identify_finds_the_crawler() {
+        let bingbot_ip = ip("157.55.39.1");
+        let ptr_record = "msnbot-157-55-39-1.search.msn.com.";
+        let resolver = MockResolver::new()
+            .with_ptr(bingbot_ip, &[ptr_record])
+            .with_addresses(ptr_record, &[bingbot_ip]);
+        let (outcome, _) = verify_by_dns(
+            &resolver,
+            bingbot_ip,
+            &every_crawler(),
+            true,
+            &PtrSuffixes::new(),
+        );
+        assert_eq!(outcome.verified_crawler(), Some("bingbot"));
+    }
+
+    #[test]
+    fn any_ptr_record_may_be_the_crawler_domain() {
+        let resolver =
+            googlebot_resolver().with_ptr(ip(GOOGLEBOT_IP), &["host.example.com.", GOOGLEBOT_PTR]);
+        let outcome = verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]);
+        assert_eq!(outcome.ptr_record(), Some(GOOGLEBOT_PTR));
+        assert_eq!(outcome.ptr_records(), ["host.example.com.", GOOGLEBOT_PTR]);
+    }
+
+    #[test]
+    fn a_ptr_record_that_is_not_a_host_name_is_not_the_crawler_domain() {
+        let attacker_ip = ip("198.51.100.30");
+        for ptr_record in [
+            "attacker.example#.googlebot.com.",
+            "attacker.example?.googlebot.com.",
+            "attacker.example&type=A&x=.googlebot.com.",
+            "attacker.example/.googlebot.com.",
+            "attacker\\.example.googlebot.com.",
+            "attacker example.googlebot.com.",
+        ] {
+            // Whoever controls the PTR record also controls where its prefix resolves.
+            let resolver = MockResolver::new()
+                .with_ptr(attacker_ip, &[ptr_record])
+                .with_addresses(ptr_record, &[attacker_ip])
+                .with_addresses("attacker.example", &[attacker_ip]);
+            assert_eq!(
+                verify(&resolver, attacker_ip, &[googlebot()]),
+                Outcome::NotCrawler {
+                    crawler: Some("googlebot"),
+                    ptr_record: ptr_record.to_string(),
+                    ptr_records: vec![ptr_record.to_string()],
+                },
+                "{}",
+                ptr_record
+            );
+        }
+    }
+
+    #[test]
+    fn host_names() {
+        assert!(is_host_name("crawl-66-249-66-1.googlebot.com."));
+        assert!(is_host_name("_service.Example.COM"));
+        assert!(!is_host_name(""));
+        assert!(!is_host_name("crawl.googlebot.com.\\000"));
+        assert!(!is_host_name("crawl.googlebot.com.%23"));
+        assert!(!is_host_name("crawl.gooɡlebot.com."));
+    }
+
+    #[test]
+    fn not_crawler_names_the_requested_crawler() {
+        let resolver = MockResolver::new().with_ptr(ip(GOOGLEBOT_IP), &["host.example.com."]);
+        assert_eq!(
+            verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]),
+            Outcome::NotCrawler {
+                crawler: Some("googlebot"),
+                ptr_record: "host.example.com.".to_string(),
+                ptr_records: vec!["host.example.com.".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn not_crawler_names_no_crawler_when_auto_identifying() {
+        let resolver = MockResolver::new().with_ptr(ip(GOOGLEBOT_IP), &["host.example.com."]);
+        let (outcome, _) = verify_by_dns(
+            &resolver,
+            ip(GOOGLEBOT_IP),
+            &every_crawler(),
+            true,
+            &PtrSuffixes::new(),
+        );
+        assert_eq!(
+            outcome,
+            Outcome::NotCrawler {
+                crawler: None,
+                ptr_record: "host.example.com.".to_string(),
+                ptr_records: vec!["host.example.com.".to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn a_crawler_domain_of_another_crawler_is_not_the_requested_crawler() {
+        let bingbot = Crawler::find("bingbot").unwrap();
+        let outcome = verify(&googlebot_resolver(), ip(GOOGLEBOT_IP), &[bingbot]);
+        assert_eq!(
+            outcome,
+            Outcome::NotCrawler {
+                crawler: Some("bingbot"),
+                ptr_record: GOOGLEBOT_PTR.to_string(),
+                ptr_records: vec![GOOGLEBOT_PTR.to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn forward_lookup_mismatch_when_the_domain_resolves_elsewhere() {
+        let resolver = googlebot_resolver().with_addresses(GOOGLEBOT_PTR, &[ip("66.249.66.2")]);
+        assert_eq!(
+            verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]),
+            Outcome::ForwardLookupMismatch {
+                crawler: "googlebot",
+                ptr_record: GOOGLEBOT_PTR.to_string(),
+                ptr_records: vec![GOOGLEBOT_PTR.to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn forward_lookup_mismatch_when_the_domain_does_not_exist() {
+        let resolver = MockResolver::new().with_ptr(ip(GOOGLEBOT_IP), &[GOOGLEBOT_PTR]);
+        assert_eq!(
+            verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]).code(),
+            "forward_lookup_mismatch"
+        );
+    }
+
+    #[test]
+    fn forward_lookup_only_considers_the_ip_version_of_the_client() {
+        // An AAAA record holding the client's IPv4 address in mapped form does not confirm it.
+        let resolver =
+            googlebot_resolver().with_addresses(GOOGLEBOT_PTR, &[ip("::ffff:66.249.66.1")]);
+        assert_eq!(
+            verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]).code(),
+            "forward_lookup_mismatch"
+        );
+    }
+
+    #[test]
+    fn no_ptr_answer() {
+        let resolver = MockResolver::new().with_ptr(ip(GOOGLEBOT_IP), &[]);
+        assert_eq!(
+            verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]),
+            Outcome::NoPtrAnswer
+        );
+    }
+
+    #[test]
+    fn ptr_nxdomain() {
+        assert_eq!(
+            verify(&MockResolver::new(), ip(GOOGLEBOT_IP), &[googlebot()]),
+            Outcome::PtrNxDomain
+        );
+    }
+
+    #[test]
+    fn dns_errors_of_the_reverse_lookup() {
+        let reverse_lookup_name = reverse_lookup_name(ip(GOOGLEBOT_IP));
+        for (error, expected) in [
+            (DnsError::Unavailable, Outcome::GoogleDnsFailed),
+            (DnsError::Rejected, Outcome::GoogleDnsFailed),
+            (DnsError::Truncated, Outcome::GoogleDnsFailed),
+            (DnsError::ErrorStatus, Outcome::GoogleDnsFailed),
+            (DnsError::Timeout, Outcome::DnsTimeout),
+            (DnsError::InvalidResponse, Outcome::InvalidDnsResponse),
+            (DnsError::InvalidName, Outcome::InvalidDnsResponse),
+            (DnsError::ServerFailure, Outcome::DnsServerFailure),
+            (DnsError::RateLimited, Outcome::DnsRateLimited),
+        ] {
+            let resolver =
+                MockResolver::new().with_error(&reverse_lookup_name, RecordType::Ptr, error);
+            let (outcome, resolver) = verify_by_dns(
+                &resolver,
+                ip(GOOGLEBOT_IP),
+                &[googlebot()],
+                false,
+                &PtrSuffixes::new(),
+            );
+            assert_eq!(outcome, expected, "{:?}", error);
+            assert_eq!(resolver, None);
+        }
+    }
+
+    #[test]
+    fn dns_error_of_the_forward_lookup() {
+        let resolver =
+            googlebot_resolver().with_error(GOOGLEBOT_PTR, RecordType::A, DnsError::Timeout);
+        let (outcome, resolver) = verify_by_dns(
+            &resolver,
+            ip(GOOGLEBOT_IP),
+            &[googlebot()],
+            false,
+            &PtrSuffixes::new(),
+        );
+        assert_eq!(outcome, Outcome::DnsTimeout);
+        assert_eq!(resolver, Some(MOCK_RESOLVER));
+    }
+
+    #[test]
+    fn ptr_suffixes_replace_the_built_in_ones() {
+        let ptr_suffixes =
+            PtrSuffixes::from([("googlebot", vec![".crawl.example.com.".to_string()])]);
+        let ptr_record = "66-249-66-1.crawl.example.com.";
+        let resolver = googlebot_resolver()
+            .with_ptr(ip(GOOGLEBOT_IP), &[ptr_record])
+            .with_addresses(ptr_record, &[ip(GOOGLEBOT_IP)]);
+        let (outcome, _) = verify_by_dns(
+            &resolver,
+            ip(GOOGLEBOT_IP),
+            &[googlebot()],
+            false,
+            &ptr_suffixes,
+        );
+        assert_eq!(outcome.verified_crawler(), Some("googlebot"));
+
+        let (outcome, _) = verify_by_dns(
+            &googlebot_resolver(),
+            ip(GOOGLEBOT_IP),
+            &[googlebot()],
+            false,
+            &ptr_suffixes,
+        );
+        assert_eq!(outcome.code(), "not_crawler");
+    }
+
+    #[test]
+    fn published_ip_ranges_answer_without_dns() {
+        let mut ip_ranges = IpRanges::default();
+        ip_ranges.insert("66.249.64.0/19", (googlebot(), "googlebot"));
+        // No DNS answer at all: the IP would be a DNS failure if it were queried.
+        let resolver = MockResolver::new();
+        let verify_ip = |ip| {
+            verify_ip(
+                &resolver,
+                &ip_ranges,
+                ip,
+                &[googlebot()],
+                false,
+                &PtrSuffixes::new(),
+            )
+        };
+
+        assert_eq!(
+            verify_ip(ip(GOOGLEBOT_IP)),
+            (
+                Outcome::InPublishedIpRange {
+                    crawler: "googlebot",
+                    list: "googlebot",
+                    prefix: "66.249.64.0/19".to_string(),
+                },
+                None
+            )
+        );
+        assert_eq!(
+            verify_ip(ip("192.0.2.1")).0,
+            verify(&resolver, ip("192.0.2.1"), &[googlebot()])
+        );
+    }
+
+    #[test]
+    fn tries_every_ptr_record_of_a_crawler_domain() {
+        let other_ptr = "crawl-192-0-2-1.googlebot.com.";
+        let resolver = MockResolver::new()
+            .with_ptr(ip(GOOGLEBOT_IP), &[other_ptr, GOOGLEBOT_PTR])
+            .with_addresses(other_ptr, &[ip("192.0.2.1")])
+            .with_addresses(GOOGLEBOT_PTR, &[ip(GOOGLEBOT_IP)]);
+        assert_eq!(
+            verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]),
+            Outcome::IsCrawler {
+                crawler: "googlebot",
+                ptr_record: GOOGLEBOT_PTR.to_string(),
+                ptr_records: vec![other_ptr.to_string(), GOOGLEBOT_PTR.to_string()],
+                ttl: MOCK_TTL,
+            }
+        );
+
+        // When none of them resolves back, the last one tried is reported.
+        let resolver = MockResolver::new()
+            .with_ptr(ip(GOOGLEBOT_IP), &[GOOGLEBOT_PTR, other_ptr])
+            .with_addresses(other_ptr, &[ip("192.0.2.1")])
+            .with_addresses(GOOGLEBOT_PTR, &[ip("192.0.2.2")]);
+        assert_eq!(
+            verify(&resolver, ip(GOOGLEBOT_IP), &[googlebot()]),
+            Outcome::ForwardLookupMismatch {
+                crawler: "googlebot",
+                ptr_record: other_ptr.to_string(),
+                ptr_records: vec![GOOGLEBOT_PTR.to_string(), other_ptr.to_string()],
+            }
+        );
+    }
+
+    #[test]
+    fn the_ttl_is_the_lowest_of_both_answers() {
+        let forward_answer = DnsResponse::from_json(
+            r#"{"Status":0,"Answer":[{"type":1,"TTL":60,"data":"66.249.66.1"}]}"#,
+        )
+        .unwrap();
+        let forward_lookup = ForwardLookup {
+            crawler: googlebot(),
+            ptr_record: GOOGLEBOT_PTR.to_string(),
+            ptr_records: vec![GOOGLEBOT_PTR.to_string()],
+            ptr_ttl: 3600,
+            next: VecDeque::new(),
+        };
+        let outcome = match forward_lookup.decide(&forward_answer, ip(GOOGLEBOT_IP)) {
+            PtrDecision::Decided(outcome) => outcome,
+            PtrDecision::NeedsForwardLookup(_) => panic!("no other PTR record to confirm"),
+        };
+        assert_eq!(
+            outcome,
+            Outcome::IsCrawler {
+                crawler: "googlebot",
+                ptr_record: GOOGLEBOT_PTR.to_string(),
+                ptr_records: vec![GOOGLEBOT_PTR.to_string()],
+                ttl: 60,
+            }
+        );
+    }
+}